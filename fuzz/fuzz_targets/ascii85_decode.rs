@@ -0,0 +1,16 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use onion::ascii85::{decode_all, DecodeOptions, ALPHABETS};
+
+fuzz_target!(|data: &[u8]| {
+    // the first byte picks the options, the rest is the input
+    if let Some((&flags, input)) = data.split_first() {
+        let options = DecodeOptions {
+            allow_overflow: flags & 1 != 0,
+            bare: flags & 2 != 0,
+        };
+        let alphabet = ALPHABETS[(flags >> 2) as usize % ALPHABETS.len()];
+        let _ = alphabet.decode(input, options);
+        let _ = decode_all(input, options);
+    }
+});
@@ -0,0 +1,7 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use onion::layers::layer2::combine;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = combine(data);
+});
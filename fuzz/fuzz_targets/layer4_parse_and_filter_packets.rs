@@ -0,0 +1,7 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use onion::layers::layer4::{filter::Filter, parse_and_filter_packets};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse_and_filter_packets(data, &Filter::DEFAULT);
+});
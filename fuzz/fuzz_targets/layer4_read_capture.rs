@@ -0,0 +1,11 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use onion::layers::layer4::{
+    filter::Filter, parse_and_filter_captured_packets, pcap::read_capture,
+};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(captured) = read_capture(data) {
+        let _ = parse_and_filter_captured_packets(&captured, &Filter::DEFAULT);
+    }
+});
@@ -0,0 +1,365 @@
+use super::encode_chunk;
+use super::{Ascii85Reader, Ascii85Writer};
+use super::{DecodeError, DecodeOptions, EncodeOptions};
+use std::io::{self, prelude::*};
+use std::net::Ipv6Addr;
+
+// marks bytes that aren't part of an alphabet in the reverse lookup table
+const NOT_A_SYMBOL: u8 = 0xff;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Padding {
+    // a short final group is written with one more character than it has bytes (Adobe, RFC 1924)
+    Truncate,
+    // the input is padded with zeroes to a whole group and the real length goes in the trailer (btoa)
+    Trailer,
+    // the input has to be a whole number of groups already (Z85)
+    Forbidden,
+}
+
+// Everything that differs between the base 85 variants. The group arithmetic is the same for all
+// of them, only the symbols, framing and padding change.
+#[derive(Debug)]
+pub struct Base85Alphabet {
+    pub name: &'static str,
+    symbols: &'static [u8; 85],
+    digits: [u8; 256],
+    pub start_delimiter: Option<&'static [u8]>,
+    pub end_delimiter: Option<&'static [u8]>,
+    // stands in for a whole group of zero bytes
+    pub zero_shorthand: Option<u8>,
+    // stands in for a whole group of spaces
+    pub space_shorthand: Option<u8>,
+    pub padding: Padding,
+    // btoa puts its header and trailer on lines of their own
+    pub delimiter_lines: bool,
+    // how each variant conventionally lays out its output
+    pub encode_options: EncodeOptions,
+}
+
+pub static ADOBE: Base85Alphabet = Base85Alphabet {
+    name: "adobe",
+    symbols: ADOBE_SYMBOLS,
+    digits: digit_table(ADOBE_SYMBOLS),
+    start_delimiter: Some(b"<~"),
+    end_delimiter: Some(b"~>"),
+    zero_shorthand: Some(b'z'),
+    // not part of Adobe's spec, but unambiguous and other tools emit it
+    space_shorthand: Some(b'y'),
+    padding: Padding::Truncate,
+    delimiter_lines: false,
+    // the onion wraps its payloads at 60 columns and doesn't use the shorthands
+    encode_options: EncodeOptions {
+        line_width: Some(60),
+        compress_zeroes: false,
+        compress_spaces: false,
+    },
+};
+
+pub static BTOA: Base85Alphabet = Base85Alphabet {
+    name: "btoa",
+    symbols: ADOBE_SYMBOLS,
+    digits: digit_table(ADOBE_SYMBOLS),
+    start_delimiter: Some(b"xbtoa Begin"),
+    // followed by " N <len> <len in hex> E <xor> S <sum> R <rotating sum>" on the same line
+    end_delimiter: Some(b"xbtoa End"),
+    zero_shorthand: Some(b'z'),
+    space_shorthand: Some(b'y'),
+    padding: Padding::Trailer,
+    delimiter_lines: true,
+    encode_options: EncodeOptions {
+        line_width: Some(78),
+        compress_zeroes: true,
+        compress_spaces: true,
+    },
+};
+
+// https://rfc.zeromq.org/spec/32/
+pub static Z85: Base85Alphabet = Base85Alphabet {
+    name: "z85",
+    symbols: Z85_SYMBOLS,
+    digits: digit_table(Z85_SYMBOLS),
+    start_delimiter: None,
+    end_delimiter: None,
+    zero_shorthand: None,
+    space_shorthand: None,
+    padding: Padding::Forbidden,
+    delimiter_lines: false,
+    encode_options: EncodeOptions {
+        line_width: None,
+        compress_zeroes: false,
+        compress_spaces: false,
+    },
+};
+
+// https://tools.ietf.org/html/rfc1924, applied to byte streams 4 bytes at a time the way git and
+// python's b85encode do. see `encode_ipv6` for the RFC's own 128 bit encoding
+pub static RFC1924: Base85Alphabet = Base85Alphabet {
+    name: "rfc1924",
+    symbols: RFC1924_SYMBOLS,
+    digits: digit_table(RFC1924_SYMBOLS),
+    start_delimiter: None,
+    end_delimiter: None,
+    zero_shorthand: None,
+    space_shorthand: None,
+    padding: Padding::Truncate,
+    delimiter_lines: false,
+    encode_options: EncodeOptions {
+        line_width: None,
+        compress_zeroes: false,
+        compress_spaces: false,
+    },
+};
+
+pub static ALPHABETS: [&Base85Alphabet; 4] = [&ADOBE, &BTOA, &Z85, &RFC1924];
+
+const ADOBE_SYMBOLS: &[u8; 85] =
+    b"!\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstu";
+const Z85_SYMBOLS: &[u8; 85] =
+    b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+const RFC1924_SYMBOLS: &[u8; 85] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+const fn digit_table(symbols: &[u8; 85]) -> [u8; 256] {
+    let mut digits = [NOT_A_SYMBOL; 256];
+    let mut i = 0;
+    while i < symbols.len() {
+        digits[symbols[i] as usize] = i as u8;
+        i += 1;
+    }
+    digits
+}
+
+impl Base85Alphabet {
+    pub fn by_name(name: &str) -> Option<&'static Base85Alphabet> {
+        ALPHABETS
+            .iter()
+            .copied()
+            .find(|alphabet| alphabet.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn symbol(&self, digit: u8) -> u8 {
+        self.symbols[digit as usize]
+    }
+
+    pub fn digit(&self, symbol: u8) -> Option<u8> {
+        match self.digits[symbol as usize] {
+            NOT_A_SYMBOL => None,
+            digit => Some(digit),
+        }
+    }
+
+    pub fn encode(&'static self, bytes: &[u8], options: EncodeOptions) -> io::Result<Vec<u8>> {
+        let mut writer = Ascii85Writer::with_alphabet(Vec::new(), self, options);
+        writer.write_all(bytes)?;
+        writer.finish()
+    }
+
+    pub fn decode(
+        &'static self,
+        bytes: &[u8],
+        options: DecodeOptions,
+    ) -> Result<Vec<u8>, DecodeError> {
+        let mut decoded = Vec::with_capacity(bytes.len() / 5 * 4);
+        Ascii85Reader::with_alphabet(bytes, self, options).read_to_end(&mut decoded)?;
+        Ok(decoded)
+    }
+}
+
+// the running checksums btoa writes in its trailer, over every byte including the padding
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(super) struct BtoaChecksum {
+    pub xor: u32,
+    pub sum: u32,
+    pub rotating: u32,
+}
+
+impl BtoaChecksum {
+    pub fn update(&mut self, byte: u8) {
+        self.xor ^= byte as u32;
+        self.sum = self.sum.wrapping_add(byte as u32).wrapping_add(1);
+        self.rotating = self.rotating.rotate_left(1).wrapping_add(byte as u32);
+    }
+
+    pub fn trailer(&self, len: u64) -> String {
+        format!(
+            " N {} {:x} E {:x} S {:x} R {:x}",
+            len, len, self.xor, self.sum, self.rotating
+        )
+    }
+
+    // the length and checksum fields of a trailer, None if it's malformed
+    pub fn parse_trailer(trailer: &str) -> Option<(u64, BtoaChecksum)> {
+        let fields: Vec<&str> = trailer.split_whitespace().collect();
+        match fields.as_slice() {
+            ["N", len, len_hex, "E", xor, "S", sum, "R", rotating] => {
+                let len: u64 = len.parse().ok()?;
+                if u64::from_str_radix(len_hex, 16).ok()? != len {
+                    return None;
+                }
+
+                let checksum = BtoaChecksum {
+                    xor: u32::from_str_radix(xor, 16).ok()?,
+                    sum: u32::from_str_radix(sum, 16).ok()?,
+                    rotating: u32::from_str_radix(rotating, 16).ok()?,
+                };
+                Some((len, checksum))
+            }
+            _ => None,
+        }
+    }
+}
+
+// RFC 1924 proper treats an address as one 128 bit number rather than four 32 bit groups
+pub fn encode_ipv6(address: Ipv6Addr) -> String {
+    let mut value = u128::from(address);
+    let mut encoded = [0u8; 20];
+
+    for symbol in encoded.iter_mut().rev() {
+        *symbol = RFC1924.symbol((value % 85) as u8);
+        value /= 85;
+    }
+
+    encoded.iter().map(|&symbol| symbol as char).collect()
+}
+
+pub fn decode_ipv6(encoded: &str) -> Result<Ipv6Addr, DecodeError> {
+    if encoded.len() != 20 {
+        return Err(DecodeError::PartialGroup { offset: 0 });
+    }
+
+    let mut value: u128 = 0;
+    for (offset, byte) in encoded.bytes().enumerate() {
+        let digit = RFC1924.digit(byte).ok_or(DecodeError::InvalidByte {
+            offset: offset as u64,
+            byte,
+        })?;
+
+        value = value
+            .checked_mul(85)
+            .and_then(|value| value.checked_add(digit as u128))
+            .ok_or(DecodeError::GroupOverflow { offset: 0 })?;
+    }
+
+    Ok(Ipv6Addr::from(value))
+}
+
+// decodes a single group of symbols, the core without any framing
+#[cfg(test)]
+fn decode_symbols(
+    alphabet: &Base85Alphabet,
+    symbols: &[u8; 5],
+    allow_overflow: bool,
+) -> Option<[u8; 4]> {
+    let mut digits = [0u8; 5];
+    for (digit, &symbol) in digits.iter_mut().zip(symbols) {
+        *digit = alphabet.digit(symbol)?;
+    }
+    super::decode_chunk(&digits, allow_overflow)
+}
+
+// encodes a single group of bytes, without the shorthands or any framing
+pub(super) fn encode_symbols(alphabet: &Base85Alphabet, bytes: &[u8; 4]) -> [u8; 5] {
+    let mut symbols = encode_chunk(bytes);
+    for symbol in symbols.iter_mut() {
+        *symbol = alphabet.symbol(*symbol);
+    }
+    symbols
+}
+
+#[test]
+fn test_digit_tables() {
+    for alphabet in ALPHABETS.iter() {
+        for digit in 0..85 {
+            assert_eq!(alphabet.digit(alphabet.symbol(digit)), Some(digit));
+        }
+        assert_eq!(alphabet.digit(b' '), None);
+        assert_eq!(alphabet.digit(b'\n'), None);
+    }
+}
+
+#[test]
+fn test_group_in_each_alphabet() {
+    // the same digits, spelled differently
+    assert_eq!(&encode_symbols(&ADOBE, b"sure"), b"F*2M7");
+    assert_eq!(&encode_symbols(&Z85, b"sure"), b"B9hIm");
+    assert_eq!(&encode_symbols(&RFC1924, b"sure"), b"b9HiM");
+
+    assert_eq!(decode_symbols(&Z85, b"B9hIm", false), Some(*b"sure"));
+    assert_eq!(decode_symbols(&Z85, b"F*2M~", false), None);
+}
+
+#[test]
+fn test_z85() -> anyhow::Result<()> {
+    // the test vector from the spec
+    let bytes = [0x86, 0x4f, 0xd2, 0x6f, 0xb5, 0x59, 0xf7, 0x5b];
+    let options = EncodeOptions::for_alphabet(&Z85);
+
+    assert_eq!(Z85.encode(&bytes, options)?, b"HelloWorld");
+    assert_eq!(Z85.decode(b"HelloWorld", DecodeOptions::default())?, bytes);
+
+    // no partial groups either way
+    assert!(Z85.encode(b"sure.", options).is_err());
+    assert!(matches!(
+        Z85.decode(b"HelloWor", DecodeOptions::default()),
+        Err(DecodeError::PartialGroup { offset: 5 })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_rfc1924() -> anyhow::Result<()> {
+    let options = EncodeOptions::for_alphabet(&RFC1924);
+
+    assert_eq!(RFC1924.encode(b"sure.", options)?, b"b9HiME&");
+    assert_eq!(
+        RFC1924.decode(b"b9HiME&", DecodeOptions::default())?,
+        b"sure.".to_vec()
+    );
+
+    // the example from the RFC
+    let address: Ipv6Addr = "1080:0:0:0:8:800:200C:417A".parse()?;
+    assert_eq!(encode_ipv6(address), "4)+k&C#VzJ4br>0wv%Yp");
+    assert_eq!(decode_ipv6("4)+k&C#VzJ4br>0wv%Yp")?, address);
+
+    Ok(())
+}
+
+#[test]
+fn test_btoa() -> anyhow::Result<()> {
+    let input = b"sure\0\0\0\0    .";
+    let encoded = BTOA.encode(input, EncodeOptions::for_alphabet(&BTOA))?;
+
+    let mut checksum = BtoaChecksum::default();
+    input
+        .iter()
+        .chain(&[0, 0, 0])
+        .for_each(|&byte| checksum.update(byte));
+
+    // the final group is padded out with zeroes, the trailer says how many bytes are real
+    let expected = format!(
+        "xbtoa Begin\nF*2M7zy/cYkO\nxbtoa End{}\n",
+        checksum.trailer(13)
+    );
+    assert_eq!(String::from_utf8(encoded.clone())?, expected);
+    assert_eq!(BTOA.decode(&encoded, DecodeOptions::default())?, input);
+
+    // a tampered trailer is caught
+    let tampered = String::from_utf8(encoded)?.replace(
+        &format!(" E {:x} ", checksum.xor),
+        &format!(" E {:x} ", checksum.xor ^ 1),
+    );
+    assert!(matches!(
+        BTOA.decode(tampered.as_bytes(), DecodeOptions::default()),
+        Err(DecodeError::ChecksumMismatch)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_by_name() {
+    assert_eq!(Base85Alphabet::by_name("Z85").map(|a| a.name), Some("z85"));
+    assert!(Base85Alphabet::by_name("base64").is_none());
+}
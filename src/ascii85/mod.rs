@@ -0,0 +1,523 @@
+mod alphabet;
+mod reader;
+mod writer;
+
+pub use alphabet::{
+    decode_ipv6, encode_ipv6, Base85Alphabet, Padding, ADOBE, ALPHABETS, BTOA, RFC1924, Z85,
+};
+pub use reader::Ascii85Reader;
+pub use writer::Ascii85Writer;
+
+#[cfg(test)]
+use anyhow::Result;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, prelude::*, ErrorKind::InvalidInput};
+
+const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+// offsets are positions in the original input, counting delimiters and whitespace
+#[derive(Debug)]
+pub enum DecodeError {
+    MissingStartDelimiter,
+    MissingEndDelimiter,
+    // an end delimiter with no start delimiter before it
+    EndBeforeStart { offset: u64 },
+    InvalidByte { offset: u64, byte: u8 },
+    // the start of an end delimiter that isn't followed by the rest of it, e.g. '~' without '>'
+    InvalidEndDelimiter { offset: u64 },
+    // 'z' or 'y' somewhere other than the start of a group
+    MisplacedShorthand { offset: u64, byte: u8 },
+    // a group whose value doesn't fit in 32 bits, offset is where the group starts
+    GroupOverflow { offset: u64 },
+    // a short final group in a variant that doesn't allow them
+    PartialGroup { offset: u64 },
+    // btoa's "xbtoa End" line, offset is where it starts
+    InvalidTrailer { offset: u64 },
+    ChecksumMismatch,
+    Io(io::Error),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::MissingStartDelimiter => write!(f, "missing Ascii85 start delimiter '<~'"),
+            DecodeError::MissingEndDelimiter => write!(f, "missing Ascii85 end delimiter '~>'"),
+            DecodeError::EndBeforeStart { offset } => write!(
+                f,
+                "found an Ascii85 end delimiter before any start delimiter at offset {}",
+                offset
+            ),
+            DecodeError::InvalidByte { offset, byte } if byte.is_ascii_graphic() => write!(
+                f,
+                "found '{}' outside of Ascii85 range at offset {}",
+                *byte as char, offset
+            ),
+            DecodeError::InvalidByte { offset, byte } => write!(
+                f,
+                "found byte {:#04x} outside of Ascii85 range at offset {}",
+                byte, offset
+            ),
+            DecodeError::InvalidEndDelimiter { offset } => {
+                write!(f, "'~' not followed by '>' at offset {}", offset)
+            }
+            DecodeError::MisplacedShorthand { offset, byte } => write!(
+                f,
+                "'{}' found in the middle of a group at offset {}",
+                *byte as char, offset
+            ),
+            DecodeError::GroupOverflow { offset } => {
+                write!(f, "group at offset {} is larger than 2^32 - 1", offset)
+            }
+            DecodeError::PartialGroup { offset } => {
+                write!(f, "incomplete group at offset {}", offset)
+            }
+            DecodeError::InvalidTrailer { offset } => {
+                write!(f, "malformed btoa trailer at offset {}", offset)
+            }
+            DecodeError::ChecksumMismatch => write!(f, "btoa checksums don't match the data"),
+            DecodeError::Io(e) => write!(f, "error reading Ascii85 input: {}", e),
+        }
+    }
+}
+
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DecodeError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// Ascii85Reader has to report errors through io::Error, unwrap them again where we can
+impl From<io::Error> for DecodeError {
+    fn from(e: io::Error) -> Self {
+        if e.get_ref().is_some_and(|inner| inner.is::<DecodeError>()) {
+            let inner = e.into_inner().expect("checked above");
+            *inner.downcast::<DecodeError>().expect("checked above")
+        } else {
+            DecodeError::Io(e)
+        }
+    }
+}
+
+impl From<DecodeError> for io::Error {
+    fn from(e: DecodeError) -> Self {
+        match e {
+            DecodeError::Io(e) => e,
+            e => io::Error::new(InvalidInput, e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EncodeOptions {
+    // maximum line length including the delimiters, None to emit a single line
+    pub line_width: Option<usize>,
+    // write an all-zero group as 'z' (standard Adobe shorthand)
+    pub compress_zeroes: bool,
+    // write a group of four spaces as 'y' (btoa shorthand)
+    pub compress_spaces: bool,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        ADOBE.encode_options
+    }
+}
+
+impl EncodeOptions {
+    pub fn for_alphabet(alphabet: &Base85Alphabet) -> Self {
+        alphabet.encode_options
+    }
+}
+
+pub fn encode(bytes: &[u8]) -> Vec<u8> {
+    encode_with_options(bytes, EncodeOptions::default())
+}
+
+pub fn encode_with_options(bytes: &[u8], options: EncodeOptions) -> Vec<u8> {
+    ADOBE
+        .encode(bytes, options)
+        .expect("the Adobe alphabet can encode anything into a Vec")
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DecodeOptions {
+    // keep the low 32 bits of groups that overflow instead of failing, for digging through
+    // corrupted payloads
+    pub allow_overflow: bool,
+    // the input may leave out the delimiters. decoding starts straight away (skipping a leading
+    // '<~' if there is one) and runs to '~>' or the end of the input
+    pub bare: bool,
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    decode_with_options(bytes, DecodeOptions::default())
+}
+
+pub fn decode_with_options(bytes: &[u8], options: DecodeOptions) -> Result<Vec<u8>, DecodeError> {
+    ADOBE.decode(bytes, options)
+}
+
+// every block in a document, in order
+pub fn decode_all(bytes: &[u8], options: DecodeOptions) -> Result<Vec<Vec<u8>>, DecodeError> {
+    let mut reader = Ascii85Reader::with_options(bytes, options);
+    let mut blocks = Vec::new();
+
+    loop {
+        let mut block = Vec::new();
+        match reader.read_to_end(&mut block).map_err(DecodeError::from) {
+            Ok(_) => blocks.push(block),
+            // text after the last block is fine, as long as there was a block
+            Err(DecodeError::MissingStartDelimiter) if !blocks.is_empty() => break,
+            Err(e) => return Err(e),
+        }
+
+        if reader.at_end_of_input() {
+            break;
+        }
+        reader.next_block();
+    }
+
+    Ok(blocks)
+}
+
+// takes digits rather than symbols so every alphabet can share it. None if the group overflows
+// 32 bits, unless we've been asked to truncate it
+fn decode_chunk(chunk: &[u8; 5], allow_overflow: bool) -> Option<[u8; 4]> {
+    let base: u64 = 85;
+    let digit1 = chunk[0] as u64;
+    let digit2 = chunk[1] as u64;
+    let digit3 = chunk[2] as u64;
+    let digit4 = chunk[3] as u64;
+    let digit5 = chunk[4] as u64;
+
+    let input = digit1 * base.pow(4)
+        + digit2 * base.pow(3)
+        + digit3 * base.pow(2)
+        + digit4 * base.pow(1)
+        + digit5;
+
+    if input > u32::MAX as u64 && !allow_overflow {
+        return None;
+    }
+
+    let first_byte = (input >> 24 & 0xff) as u8;
+    let second_byte = (input >> 16 & 0xff) as u8;
+    let third_byte = (input >> 8 & 0xff) as u8;
+    let fourth_byte = (input & 0xff) as u8;
+
+    Some([first_byte, second_byte, third_byte, fourth_byte])
+}
+
+// digits rather than symbols, see decode_chunk
+fn encode_chunk(chunk: &[u8; 4]) -> [u8; 5] {
+    let mut value = u32::from_be_bytes(*chunk);
+
+    // least significant digit first, so fill from the back
+    let mut digits = [0u8; 5];
+    for digit in digits.iter_mut().rev() {
+        *digit = (value % 85) as u8;
+        value /= 85;
+    }
+
+    digits
+}
+
+#[test]
+fn test_decode() -> Result<()> {
+    use std::fs::File;
+    use std::io::prelude::*;
+
+    let mut input = File::open("test/encoded.txt")?;
+    let mut encoded = Vec::new();
+    input.read_to_end(&mut encoded)?;
+
+    let output = decode(encoded.as_mut())?;
+
+    let expected = "Man is distinguished, not only by his reason, but by this singular passion from other animals, which is a lust of the mind, that by a perseverance of delight in the continued and indefatigable generation of knowledge, exceeds the short vehemence of any carnal pleasure.";
+
+    assert_eq!(String::from_utf8(output)?, expected);
+    Ok(())
+}
+
+#[test]
+fn test_encode() -> Result<()> {
+    use std::fs::File;
+    use std::io::prelude::*;
+
+    let mut input = File::open("test/encoded.txt")?;
+    let mut expected = Vec::new();
+    input.read_to_end(&mut expected)?;
+
+    let decoded = decode(&expected)?;
+    let encoded = encode_with_options(
+        &decoded,
+        EncodeOptions {
+            line_width: Some(75),
+            ..EncodeOptions::default()
+        },
+    );
+
+    assert_eq!(String::from_utf8(encoded)?, String::from_utf8(expected)?);
+    Ok(())
+}
+
+#[test]
+fn test_encode_partial_group() -> Result<()> {
+    let options = EncodeOptions {
+        line_width: None,
+        ..EncodeOptions::default()
+    };
+
+    assert_eq!(encode_with_options(b"", options), b"<~~>");
+    assert_eq!(encode_with_options(b".", options), b"<~/c~>");
+    assert_eq!(encode_with_options(b"sure", options), b"<~F*2M7~>");
+    assert_eq!(encode_with_options(b"sure.", options), b"<~F*2M7/c~>");
+
+    for input in [&b"a"[..], b"ab", b"abc", b"abcd", b"abcde"].iter() {
+        assert_eq!(&decode(&encode_with_options(input, options))?, input);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_keeps_end_delimiter_together() {
+    let options = EncodeOptions {
+        line_width: Some(6),
+        ..EncodeOptions::default()
+    };
+
+    // once a line is full, "~>" moves to the next one rather than being split
+    assert_eq!(
+        encode_with_options(b"sure", options),
+        b"<~F*2M\n7~>".to_vec()
+    );
+    assert_eq!(encode_with_options(b"sur", options), b"<~F*2L\n~>".to_vec());
+}
+
+#[test]
+fn test_decode_shorthands() -> Result<()> {
+    assert_eq!(decode(b"<~z~>")?, vec![0, 0, 0, 0]);
+    assert_eq!(decode(b"<~y~>")?, b"    ".to_vec());
+    assert_eq!(decode(b"<~F*2M7zy/c~>")?, b"sure\0\0\0\0    .".to_vec());
+
+    // the shorthands can't stand in for part of a group
+    assert!(matches!(
+        decode(b"<~F*z2M7~>"),
+        Err(DecodeError::MisplacedShorthand {
+            offset: 4,
+            byte: b'z'
+        })
+    ));
+    assert!(matches!(
+        decode(b"<~F*2M7/y~>"),
+        Err(DecodeError::MisplacedShorthand {
+            offset: 8,
+            byte: b'y'
+        })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_encode_shorthands() -> Result<()> {
+    let input = b"sure\0\0\0\0    \0\0";
+    let plain = EncodeOptions {
+        line_width: None,
+        ..EncodeOptions::default()
+    };
+    let compressed = EncodeOptions {
+        compress_zeroes: true,
+        compress_spaces: true,
+        ..plain
+    };
+
+    assert_eq!(encode_with_options(input, plain), b"<~F*2M7!!!!!+<VdL!!!~>");
+    assert_eq!(encode_with_options(input, compressed), b"<~F*2M7zy!!!~>");
+
+    assert_eq!(decode(&encode_with_options(input, compressed))?, input);
+    Ok(())
+}
+
+#[test]
+fn test_decode_error_offsets() {
+    assert!(matches!(
+        decode(b"no block here"),
+        Err(DecodeError::MissingStartDelimiter)
+    ));
+    assert!(matches!(
+        decode(b"<~F*2M7"),
+        Err(DecodeError::MissingEndDelimiter)
+    ));
+
+    // offsets count the text before the block and any whitespace inside it
+    assert!(matches!(
+        decode(b"payload: <~F*2M7\r\n/{~>"),
+        Err(DecodeError::InvalidByte {
+            offset: 19,
+            byte: b'{'
+        })
+    ));
+    assert!(matches!(
+        decode("<~F*2\u{e9}M7~>".as_bytes()),
+        Err(DecodeError::InvalidByte {
+            offset: 5,
+            byte: 0xc3
+        })
+    ));
+    assert!(matches!(
+        decode(b"<~F*2M7~~>"),
+        Err(DecodeError::InvalidEndDelimiter { offset: 7 })
+    ));
+}
+
+#[test]
+fn test_decode_error_through_anyhow() {
+    // layers use decode with `?`, callers should still be able to get at the offset
+    let result: anyhow::Result<Vec<u8>> = decode(b"<~F*2M7v~>").map_err(anyhow::Error::from);
+    let error = result.unwrap_err();
+
+    assert!(matches!(
+        error.downcast_ref::<DecodeError>(),
+        Some(DecodeError::InvalidByte {
+            offset: 7,
+            byte: b'v'
+        })
+    ));
+}
+
+#[test]
+fn test_decode_overflow() -> Result<()> {
+    // s8W-! is the largest valid group, 2^32 - 1
+    assert_eq!(decode(b"<~s8W-!~>")?, vec![0xff, 0xff, 0xff, 0xff]);
+
+    assert!(matches!(
+        decode(b"<~F*2M7s8W-\"~>"),
+        Err(DecodeError::GroupOverflow { offset: 7 })
+    ));
+    assert!(matches!(
+        decode(b"<~uuuu~>"),
+        Err(DecodeError::GroupOverflow { offset: 2 })
+    ));
+
+    // lenient mode keeps the low 32 bits like the decoder always used to
+    let lenient = DecodeOptions {
+        allow_overflow: true,
+        ..DecodeOptions::default()
+    };
+    assert_eq!(
+        decode_with_options(b"<~F*2M7s8W-\"~>", lenient)?,
+        b"sure\0\0\0\0".to_vec()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_delimiters() -> Result<()> {
+    assert!(matches!(
+        decode(b""),
+        Err(DecodeError::MissingStartDelimiter)
+    ));
+    assert!(matches!(
+        decode(b"F*2M7~> <~/c~>"),
+        Err(DecodeError::EndBeforeStart { offset: 5 })
+    ));
+
+    // the first block wins
+    assert_eq!(decode(b"<~F*2M7~>\n<~/c~>")?, b"sure");
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_all() -> Result<()> {
+    let document = b"first <~F*2M7~>\nsecond <~/c~>\nthat's all";
+    assert_eq!(
+        decode_all(document, DecodeOptions::default())?,
+        vec![b"sure".to_vec(), b".".to_vec()]
+    );
+
+    assert!(matches!(
+        decode_all(b"nothing to see", DecodeOptions::default()),
+        Err(DecodeError::MissingStartDelimiter)
+    ));
+    assert!(matches!(
+        decode_all(b"<~F*2M7~> <~/c", DecodeOptions::default()),
+        Err(DecodeError::MissingEndDelimiter)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_bare() -> Result<()> {
+    let bare = DecodeOptions {
+        bare: true,
+        ..DecodeOptions::default()
+    };
+
+    assert_eq!(decode_with_options(b"", bare)?, b"");
+    assert_eq!(decode_with_options(b"F*2M7/c", bare)?, b"sure.");
+    assert_eq!(decode_with_options(b"F*2M7/c~>", bare)?, b"sure.");
+    assert_eq!(decode_with_options(b"\n<~F*2M7/c~>", bare)?, b"sure.");
+
+    // '<' on its own is just a digit
+    assert_eq!(
+        decode_with_options(b"<<<<<", bare)?,
+        decode_with_options(b"<~<<<<<~>", DecodeOptions::default())?
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_round_trip(
+        bytes in proptest::collection::vec(0..=255u8, 0..1024),
+        line_width in proptest::option::of(0..100usize),
+        compress_zeroes: bool,
+        compress_spaces: bool,
+    ) {
+        let options = EncodeOptions { line_width, compress_zeroes, compress_spaces };
+        let encoded = encode_with_options(&bytes, options);
+        proptest::prop_assert_eq!(decode(&encoded)?, bytes);
+    }
+
+    #[test]
+    fn test_alphabet_round_trip(bytes in proptest::collection::vec(0..=255u8, 0..1024)) {
+        for alphabet in ALPHABETS.iter() {
+            let bytes = match alphabet.padding {
+                Padding::Forbidden => &bytes[..bytes.len() / 4 * 4],
+                _ => &bytes[..],
+            };
+            let encoded = alphabet.encode(bytes, EncodeOptions::for_alphabet(alphabet))?;
+            proptest::prop_assert_eq!(
+                alphabet.decode(&encoded, DecodeOptions::default())?,
+                bytes,
+                "{}",
+                alphabet.name
+            );
+        }
+    }
+
+    // anything at all can be thrown at the decoder, it just has to say no
+    #[test]
+    fn test_decode_arbitrary(
+        bytes in proptest::collection::vec(0..=255u8, 0..256),
+        allow_overflow: bool,
+        bare: bool,
+    ) {
+        let options = DecodeOptions { allow_overflow, bare };
+        for alphabet in ALPHABETS.iter() {
+            let _ = alphabet.decode(&bytes, options);
+        }
+        let _ = decode_all(&bytes, options);
+    }
+}
@@ -0,0 +1,477 @@
+use super::alphabet::{Base85Alphabet, BtoaChecksum, Padding, ADOBE};
+use super::{decode_chunk, DecodeError, DecodeOptions, DEFAULT_BUFFER_SIZE};
+#[cfg(test)]
+use anyhow::Result;
+use std::io::{self, prelude::*};
+
+// btoa trailers are about 50 bytes, anything much longer isn't one
+const MAX_TRAILER_LEN: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReaderState {
+    // skipping everything up to the start delimiter, counting how much of it we've matched. an
+    // end delimiter turning up first is an error
+    SearchingForStart { matched: usize, end_matched: usize },
+    // bare mode: the data starts straight away, unless it opens with the start delimiter anyway
+    OptionalStart { matched: usize },
+    // decoding groups, counting how much of the end delimiter we've matched
+    InData { end_matched: usize },
+    // reading the rest of the line after btoa's end delimiter
+    Trailer,
+    // the block is over, nothing after it is consumed
+    Finished,
+}
+
+// Decodes the first base 85 block found in `inner`, a buffer at a time.
+pub struct Ascii85Reader<R> {
+    inner: R,
+    alphabet: &'static Base85Alphabet,
+    options: DecodeOptions,
+    buffer: Box<[u8]>,
+    buffer_pos: usize,
+    buffer_len: usize,
+    // offset of the next byte to be read from inner
+    offset: u64,
+    eof: bool,
+    state: ReaderState,
+    end_offset: u64,
+    // digits, not symbols
+    group: [u8; 5],
+    group_len: usize,
+    group_offset: u64,
+    output: [u8; 4],
+    output_pos: usize,
+    output_len: usize,
+    // btoa only: the last group is held back until the trailer says how much of it is padding
+    pending: Option<[u8; 4]>,
+    decoded_len: u64,
+    checksum: BtoaChecksum,
+    trailer: Vec<u8>,
+}
+
+impl<R: Read> Ascii85Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Ascii85Reader::with_capacity(DEFAULT_BUFFER_SIZE, inner)
+    }
+
+    pub fn with_options(inner: R, options: DecodeOptions) -> Self {
+        Ascii85Reader::with_alphabet(inner, &ADOBE, options)
+    }
+
+    pub fn with_alphabet(
+        inner: R,
+        alphabet: &'static Base85Alphabet,
+        options: DecodeOptions,
+    ) -> Self {
+        let mut reader = Ascii85Reader::new(inner);
+        reader.alphabet = alphabet;
+        reader.options = options;
+        reader.state = ReaderState::initial(alphabet, &options);
+        reader
+    }
+
+    pub fn with_capacity(capacity: usize, inner: R) -> Self {
+        Ascii85Reader {
+            inner,
+            alphabet: &ADOBE,
+            options: DecodeOptions::default(),
+            buffer: vec![0; capacity.max(1)].into_boxed_slice(),
+            buffer_pos: 0,
+            buffer_len: 0,
+            offset: 0,
+            eof: false,
+            state: ReaderState::initial(&ADOBE, &DecodeOptions::default()),
+            end_offset: 0,
+            group: [0; 5],
+            group_len: 0,
+            group_offset: 0,
+            output: [0; 4],
+            output_pos: 0,
+            output_len: 0,
+            pending: None,
+            decoded_len: 0,
+            checksum: BtoaChecksum::default(),
+            trailer: Vec::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // once a block has been read to the end, start looking for another one after it
+    pub fn next_block(&mut self) {
+        self.state = ReaderState::initial(self.alphabet, &self.options);
+        self.group_len = 0;
+        self.output_len = 0;
+        self.output_pos = 0;
+        self.pending = None;
+        self.decoded_len = 0;
+        self.checksum = BtoaChecksum::default();
+        self.trailer.clear();
+    }
+
+    pub fn at_end_of_input(&self) -> bool {
+        self.eof && self.buffer_pos == self.buffer_len
+    }
+
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.buffer_pos == self.buffer_len {
+            self.buffer_len = self.inner.read(&mut self.buffer)?;
+            self.buffer_pos = 0;
+
+            if self.buffer_len == 0 {
+                self.eof = true;
+                return Ok(None);
+            }
+        }
+
+        let byte = self.buffer[self.buffer_pos];
+        self.buffer_pos += 1;
+        self.offset += 1;
+        Ok(Some(byte))
+    }
+
+    fn set_output(&mut self, bytes: &[u8]) {
+        self.output[..bytes.len()].copy_from_slice(bytes);
+        self.output_pos = 0;
+        self.output_len = bytes.len();
+    }
+
+    fn process(&mut self, byte: u8) -> Result<(), DecodeError> {
+        self.process_at(byte, self.offset - 1)
+    }
+
+    fn process_at(&mut self, byte: u8, offset: u64) -> Result<(), DecodeError> {
+        match self.state {
+            ReaderState::SearchingForStart {
+                matched,
+                end_matched,
+            } => {
+                let start = self.alphabet.start_delimiter.unwrap_or_default();
+                let end = self.alphabet.end_delimiter.unwrap_or_default();
+                let matched = advance_match(start, matched, byte);
+                let end_matched = advance_match(end, end_matched, byte);
+
+                if matched == start.len() {
+                    self.state = ReaderState::InData { end_matched: 0 };
+                } else if !end.is_empty() && end_matched == end.len() {
+                    return Err(DecodeError::EndBeforeStart {
+                        offset: offset + 1 - end.len() as u64,
+                    });
+                } else {
+                    self.state = ReaderState::SearchingForStart {
+                        matched,
+                        end_matched,
+                    };
+                }
+            }
+            ReaderState::OptionalStart { matched: 0 } if byte.is_ascii_whitespace() => {}
+            ReaderState::OptionalStart { matched } => {
+                let start = self.alphabet.start_delimiter.unwrap_or_default();
+                if byte == start[matched] && matched + 1 == start.len() {
+                    self.state = ReaderState::InData { end_matched: 0 };
+                } else if byte == start[matched] {
+                    self.state = ReaderState::OptionalStart {
+                        matched: matched + 1,
+                    };
+                } else {
+                    // it wasn't a delimiter after all, so what we've held back is data
+                    self.replay_optional_start(matched, offset)?;
+                    self.process_at(byte, offset)?;
+                }
+            }
+            ReaderState::InData { end_matched } if end_matched > 0 => {
+                let end = self.alphabet.end_delimiter.unwrap_or_default();
+                if byte != end[end_matched] {
+                    return Err(DecodeError::InvalidEndDelimiter {
+                        offset: self.end_offset,
+                    });
+                }
+
+                if end_matched + 1 < end.len() {
+                    self.state = ReaderState::InData {
+                        end_matched: end_matched + 1,
+                    };
+                } else if self.alphabet.padding == Padding::Trailer {
+                    self.finish_group()?;
+                    self.state = ReaderState::Trailer;
+                } else {
+                    self.finish_group()?;
+                    self.state = ReaderState::Finished;
+                }
+            }
+            ReaderState::InData { .. } => match byte {
+                _ if self.alphabet.end_delimiter.map(|end| end[0]) == Some(byte) => {
+                    self.end_offset = offset;
+                    self.state = ReaderState::InData { end_matched: 1 };
+                }
+                // silently ignore all whitespace in the encoded data
+                _ if byte.is_ascii_whitespace() => {}
+                // 'z' is four zero bytes and 'y' is four spaces, but only in place of a whole group
+                _ if Some(byte) == self.alphabet.zero_shorthand
+                    || Some(byte) == self.alphabet.space_shorthand =>
+                {
+                    if self.group_len != 0 {
+                        return Err(DecodeError::MisplacedShorthand { offset, byte });
+                    }
+                    let expanded = if Some(byte) == self.alphabet.zero_shorthand {
+                        [0u8; 4]
+                    } else {
+                        *b"    "
+                    };
+                    self.push_decoded(expanded);
+                }
+                _ => {
+                    let digit = self
+                        .alphabet
+                        .digit(byte)
+                        .ok_or(DecodeError::InvalidByte { offset, byte })?;
+
+                    if self.group_len == 0 {
+                        self.group_offset = offset;
+                    }
+                    self.group[self.group_len] = digit;
+                    self.group_len += 1;
+
+                    if self.group_len == 5 {
+                        let decoded = self.decode_group()?;
+                        self.push_decoded(decoded);
+                        self.group_len = 0;
+                    }
+                }
+            },
+            ReaderState::Trailer if byte == b'\n' => self.finish_trailer()?,
+            ReaderState::Trailer => {
+                if self.trailer.len() == MAX_TRAILER_LEN {
+                    return Err(DecodeError::InvalidTrailer {
+                        offset: self.end_offset,
+                    });
+                }
+                self.trailer.push(byte);
+            }
+            ReaderState::Finished => {}
+        }
+
+        Ok(())
+    }
+
+    fn push_decoded(&mut self, decoded: [u8; 4]) {
+        if self.alphabet.padding != Padding::Trailer {
+            self.set_output(&decoded);
+            return;
+        }
+
+        decoded.iter().for_each(|&byte| self.checksum.update(byte));
+        self.decoded_len += 4;
+
+        if let Some(previous) = self.pending.replace(decoded) {
+            self.set_output(&previous);
+        }
+    }
+
+    fn decode_group(&self) -> Result<[u8; 4], DecodeError> {
+        decode_chunk(&self.group, self.options.allow_overflow).ok_or(DecodeError::GroupOverflow {
+            offset: self.group_offset,
+        })
+    }
+
+    // pad the last group with the highest digit, then drop as many bytes as were padded
+    fn finish_group(&mut self) -> Result<(), DecodeError> {
+        if self.group_len == 0 {
+            return Ok(());
+        }
+
+        if self.alphabet.padding != Padding::Truncate {
+            return Err(DecodeError::PartialGroup {
+                offset: self.group_offset,
+            });
+        }
+
+        let pad = 5 - self.group_len;
+        for digit in self.group[self.group_len..].iter_mut() {
+            *digit = 84;
+        }
+
+        let decoded = self.decode_group()?;
+        self.set_output(&decoded[..4 - pad]);
+        self.group_len = 0;
+        Ok(())
+    }
+
+    // the trailer gives the real length, which tells us how much of the last group was padding
+    fn finish_trailer(&mut self) -> Result<(), DecodeError> {
+        let offset = self.end_offset;
+        let invalid = || DecodeError::InvalidTrailer { offset };
+
+        let trailer = std::str::from_utf8(&self.trailer).map_err(|_| invalid())?;
+        let (len, checksum) = BtoaChecksum::parse_trailer(trailer).ok_or_else(invalid)?;
+
+        let keep = match self.decoded_len.checked_sub(len) {
+            Some(padding) if padding < 4 => 4 - padding as usize,
+            _ => return Err(invalid()),
+        };
+
+        if checksum != self.checksum {
+            return Err(DecodeError::ChecksumMismatch);
+        }
+
+        if let Some(last) = self.pending.take() {
+            self.set_output(&last[..keep]);
+        }
+        self.state = ReaderState::Finished;
+        Ok(())
+    }
+
+    fn replay_optional_start(&mut self, matched: usize, offset: u64) -> Result<(), DecodeError> {
+        let start = self.alphabet.start_delimiter.unwrap_or_default();
+        self.state = ReaderState::InData { end_matched: 0 };
+
+        for (i, &byte) in start[..matched].iter().enumerate() {
+            self.process_at(byte, offset - (matched - i) as u64)?;
+        }
+        Ok(())
+    }
+
+    fn finish_input(&mut self) -> Result<(), DecodeError> {
+        if let ReaderState::OptionalStart { matched } = self.state {
+            self.replay_optional_start(matched, self.offset)?;
+        }
+
+        match self.state {
+            ReaderState::SearchingForStart { .. } => Err(DecodeError::MissingStartDelimiter),
+            // without an end delimiter the block just runs to the end of the input
+            ReaderState::InData { end_matched: 0 }
+                if self.alphabet.end_delimiter.is_none() || self.options.bare =>
+            {
+                self.finish_group()?;
+                // a bare btoa block has no trailer, so all we can do is keep the padding
+                if let Some(last) = self.pending.take() {
+                    self.set_output(&last);
+                }
+                self.state = ReaderState::Finished;
+                Ok(())
+            }
+            ReaderState::OptionalStart { .. } => unreachable!("replayed above"),
+            ReaderState::InData { .. } => Err(DecodeError::MissingEndDelimiter),
+            ReaderState::Trailer => self.finish_trailer(),
+            ReaderState::Finished => Ok(()),
+        }
+    }
+}
+
+impl ReaderState {
+    fn initial(alphabet: &Base85Alphabet, options: &DecodeOptions) -> Self {
+        match alphabet.start_delimiter {
+            Some(_) if options.bare => ReaderState::OptionalStart { matched: 0 },
+            Some(_) => ReaderState::SearchingForStart {
+                matched: 0,
+                end_matched: 0,
+            },
+            None => ReaderState::InData { end_matched: 0 },
+        }
+    }
+}
+
+// how much of `delimiter` has been matched once `byte` is added to the first `matched` bytes
+fn advance_match(delimiter: &[u8], matched: usize, byte: u8) -> usize {
+    if delimiter.get(matched) == Some(&byte) {
+        matched + 1
+    } else {
+        (delimiter.first() == Some(&byte)) as usize
+    }
+}
+
+impl<R: Read> Read for Ascii85Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut written = 0;
+
+        while written < buf.len() {
+            if self.output_pos < self.output_len {
+                let available = &self.output[self.output_pos..self.output_len];
+                let n = available.len().min(buf.len() - written);
+                buf[written..written + n].copy_from_slice(&available[..n]);
+                self.output_pos += n;
+                written += n;
+                continue;
+            }
+
+            if self.state == ReaderState::Finished {
+                break;
+            }
+
+            match self.next_byte()? {
+                Some(byte) => self.process(byte)?,
+                None => self.finish_input()?,
+            }
+        }
+
+        Ok(written)
+    }
+}
+
+#[test]
+fn test_reader_across_buffer_boundaries() -> Result<()> {
+    let mut encoded = Vec::new();
+    std::fs::File::open("test/encoded.txt")?.read_to_end(&mut encoded)?;
+    let expected = super::decode(&encoded)?;
+
+    // tiny buffers split the delimiters, whitespace and groups at every possible position
+    for capacity in 1..8 {
+        let mut decoded = Vec::new();
+        Ascii85Reader::with_capacity(capacity, encoded.as_slice()).read_to_end(&mut decoded)?;
+        assert_eq!(decoded, expected);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reader_stops_at_end_delimiter() -> Result<()> {
+    let mut reader = Ascii85Reader::with_capacity(1, &b"header <~F*2M7~> trailer"[..]);
+    let mut decoded = Vec::new();
+    reader.read_to_end(&mut decoded)?;
+    assert_eq!(decoded, b"sure");
+
+    // with a one byte buffer nothing after '~>' has been consumed
+    let mut rest = String::new();
+    reader.into_inner().read_to_string(&mut rest)?;
+    assert_eq!(rest, " trailer");
+
+    Ok(())
+}
+
+#[test]
+fn test_reader_next_block() -> Result<()> {
+    let mut reader = Ascii85Reader::new(&b"one <~F*2M7~> two <~/c~> three"[..]);
+
+    let mut first = Vec::new();
+    reader.read_to_end(&mut first)?;
+    reader.next_block();
+    let mut second = Vec::new();
+    reader.read_to_end(&mut second)?;
+
+    assert_eq!(first, b"sure");
+    assert_eq!(second, b".");
+    assert!(!reader.at_end_of_input());
+
+    reader.next_block();
+    assert!(reader.read_to_end(&mut Vec::new()).is_err());
+    assert!(reader.at_end_of_input());
+
+    Ok(())
+}
+
+#[test]
+fn test_reader_missing_delimiters() {
+    let mut decoded = Vec::new();
+    assert!(Ascii85Reader::new(&b""[..])
+        .read_to_end(&mut decoded)
+        .is_err());
+    assert!(Ascii85Reader::new(&b"F*2M7~>"[..])
+        .read_to_end(&mut decoded)
+        .is_err());
+    assert!(Ascii85Reader::new(&b"<~F*2M7"[..])
+        .read_to_end(&mut decoded)
+        .is_err());
+}
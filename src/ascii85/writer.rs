@@ -0,0 +1,253 @@
+use super::alphabet::{encode_symbols, Base85Alphabet, BtoaChecksum, Padding, ADOBE};
+use super::{EncodeOptions, DEFAULT_BUFFER_SIZE};
+#[cfg(test)]
+use anyhow::Result;
+use std::io::{self, prelude::*, Error, ErrorKind::InvalidInput};
+
+// Encodes everything written to it as a single base 85 block. Call `finish` to write the final
+// group and the end delimiter, otherwise they are written (ignoring errors) when dropped.
+pub struct Ascii85Writer<W: Write> {
+    inner: Option<W>,
+    alphabet: &'static Base85Alphabet,
+    options: EncodeOptions,
+    group: [u8; 4],
+    group_len: usize,
+    column: usize,
+    started: bool,
+    output: Vec<u8>,
+    // btoa only
+    written_len: u64,
+    checksum: BtoaChecksum,
+}
+
+impl<W: Write> Ascii85Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Ascii85Writer::with_options(inner, EncodeOptions::default())
+    }
+
+    pub fn with_options(inner: W, options: EncodeOptions) -> Self {
+        Ascii85Writer::with_alphabet(inner, &ADOBE, options)
+    }
+
+    pub fn with_alphabet(
+        inner: W,
+        alphabet: &'static Base85Alphabet,
+        options: EncodeOptions,
+    ) -> Self {
+        Ascii85Writer {
+            inner: Some(inner),
+            alphabet,
+            options,
+            group: [0; 4],
+            group_len: 0,
+            column: 0,
+            started: false,
+            output: Vec::with_capacity(DEFAULT_BUFFER_SIZE),
+            written_len: 0,
+            checksum: BtoaChecksum::default(),
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        let result = self.write_end();
+        let inner = self.inner.take().expect("inner writer is only taken once");
+        result.map(|_| inner)
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if let Some(width) = self.options.line_width {
+                if width > 0 && self.column >= width {
+                    self.output.push(b'\n');
+                    self.column = 0;
+                }
+            }
+            self.output.push(byte);
+            self.column += 1;
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.output.push(b'\n');
+        self.column = 0;
+    }
+
+    fn write_start(&mut self) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+
+        if let Some(start) = self.alphabet.start_delimiter {
+            // like the end delimiter, the start delimiter is never split across lines
+            self.output.extend_from_slice(start);
+            self.column += start.len();
+            if self.alphabet.delimiter_lines {
+                self.new_line();
+            }
+        }
+    }
+
+    fn write_group(&mut self) {
+        if self.alphabet.padding == Padding::Trailer {
+            let group = self.group;
+            group.iter().for_each(|&byte| self.checksum.update(byte));
+        }
+
+        let zero_shorthand = self
+            .alphabet
+            .zero_shorthand
+            .filter(|_| self.options.compress_zeroes);
+        let space_shorthand = self
+            .alphabet
+            .space_shorthand
+            .filter(|_| self.options.compress_spaces);
+
+        match (&self.group, zero_shorthand, space_shorthand) {
+            ([0, 0, 0, 0], Some(shorthand), _) => self.emit(&[shorthand]),
+            (b"    ", _, Some(shorthand)) => self.emit(&[shorthand]),
+            (group, _, _) => {
+                let encoded = encode_symbols(self.alphabet, group);
+                self.emit(&encoded);
+            }
+        }
+        self.group_len = 0;
+    }
+
+    fn write_last_group(&mut self) -> io::Result<()> {
+        if self.group_len == 0 {
+            return Ok(());
+        }
+
+        match self.alphabet.padding {
+            // the shorthands only apply to full groups, so the last chunk is always written out.
+            // pad it with zeroes, then only keep one more character than there were input bytes
+            Padding::Truncate => {
+                let mut last = [0u8; 4];
+                last[..self.group_len].copy_from_slice(&self.group[..self.group_len]);
+                let encoded = encode_symbols(self.alphabet, &last);
+                self.emit(&encoded[..=self.group_len]);
+                self.group_len = 0;
+            }
+            // pad with zeroes and write a whole group, the trailer has the real length
+            Padding::Trailer => {
+                for byte in self.group[self.group_len..].iter_mut() {
+                    *byte = 0;
+                }
+                self.write_group();
+            }
+            Padding::Forbidden => {
+                return Err(Error::new(
+                    InvalidInput,
+                    format!(
+                        "{} input must be a multiple of 4 bytes, {} left over",
+                        self.alphabet.name, self.group_len
+                    ),
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_end(&mut self) -> io::Result<()> {
+        self.write_start();
+        self.write_last_group()?;
+
+        if let Some(end) = self.alphabet.end_delimiter {
+            if self.alphabet.delimiter_lines {
+                if self.column > 0 {
+                    self.new_line();
+                }
+                self.output.extend_from_slice(end);
+            } else {
+                // don't split the end delimiter across lines
+                if let Some(width) = self.options.line_width {
+                    if width > 0 && self.column + end.len() > width {
+                        self.new_line();
+                    }
+                }
+                self.output.extend_from_slice(end);
+                self.column += end.len();
+            }
+        }
+
+        if self.alphabet.padding == Padding::Trailer {
+            let trailer = self.checksum.trailer(self.written_len);
+            self.output.extend_from_slice(trailer.as_bytes());
+            self.new_line();
+        }
+
+        self.flush_output()?;
+        if let Some(inner) = self.inner.as_mut() {
+            inner.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush_output(&mut self) -> io::Result<()> {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.write_all(&self.output)?;
+        }
+        self.output.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Ascii85Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_start();
+
+        for &byte in buf {
+            self.group[self.group_len] = byte;
+            self.group_len += 1;
+
+            if self.group_len == 4 {
+                self.write_group();
+            }
+        }
+
+        self.written_len += buf.len() as u64;
+        self.flush_output()?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_output()?;
+        match self.inner.as_mut() {
+            Some(inner) => inner.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for Ascii85Writer<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.write_end();
+        }
+    }
+}
+
+#[test]
+fn test_writer_matches_encode() -> Result<()> {
+    use super::{decode, encode_with_options};
+
+    let input: Vec<u8> = (0..=255).cycle().take(1000).collect();
+    let options = EncodeOptions {
+        line_width: Some(75),
+        ..EncodeOptions::default()
+    };
+
+    // write in uneven pieces so groups straddle calls to write
+    let mut writer = Ascii85Writer::with_options(Vec::new(), options);
+    for piece in input.chunks(7) {
+        writer.write_all(piece)?;
+    }
+    let encoded = writer.finish()?;
+
+    assert_eq!(encoded, encode_with_options(&input, options));
+    assert_eq!(decode(&encoded)?, input);
+
+    Ok(())
+}
@@ -0,0 +1,242 @@
+// packing and unpacking symbols that don't line up with bytes, 1 to 32 bits at a time
+
+use anyhow::{ensure, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOrder {
+    // the high bit of each byte comes first, and symbols are written high bit first. the way
+    // base32 and base64 pack bits
+    MsbFirst,
+    // the low bit of each byte comes first, and symbols are written low bit first. the way
+    // DEFLATE packs bits
+    LsbFirst,
+}
+
+fn mask(bits: u32) -> u64 {
+    (1 << bits) - 1
+}
+
+fn check_width(bits: u32) -> Result<()> {
+    ensure!(
+        (1..=32).contains(&bits),
+        "symbols can be 1 to 32 bits, not {}",
+        bits
+    );
+    Ok(())
+}
+
+// reads `bits` bit symbols, and iterates over every whole symbol left. leftover bits are dropped
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    order: BitOrder,
+    bits: u32,
+    // the low `buffered` bits are read from bytes but not handed out yet
+    buffer: u64,
+    buffered: u32,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(bytes: &'a [u8], order: BitOrder, bits: u32) -> Result<Self> {
+        check_width(bits)?;
+        Ok(BitReader {
+            bytes,
+            order,
+            bits,
+            buffer: 0,
+            buffered: 0,
+        })
+    }
+
+    pub fn bits_left(&self) -> usize {
+        self.bytes.len() * 8 + self.buffered as usize
+    }
+
+    // None once there are fewer than `bits` bits left
+    pub fn read(&mut self) -> Option<u32> {
+        let bits = self.bits;
+        if self.bits_left() < bits as usize {
+            return None;
+        }
+
+        while self.buffered < bits {
+            let (&byte, rest) = self.bytes.split_first()?;
+            self.bytes = rest;
+            match self.order {
+                BitOrder::MsbFirst => self.buffer = self.buffer << 8 | byte as u64,
+                BitOrder::LsbFirst => self.buffer |= (byte as u64) << self.buffered,
+            }
+            self.buffered += 8;
+        }
+
+        self.buffered -= bits;
+        let symbol = match self.order {
+            BitOrder::MsbFirst => (self.buffer >> self.buffered) & mask(bits),
+            BitOrder::LsbFirst => {
+                let symbol = self.buffer & mask(bits);
+                self.buffer >>= bits;
+                symbol
+            }
+        };
+        Some(symbol as u32)
+    }
+}
+
+impl Iterator for BitReader<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        self.read()
+    }
+}
+
+// writes `bits` bit symbols
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    order: BitOrder,
+    bits: u32,
+    // the low `buffered` bits don't make a whole byte yet
+    buffer: u64,
+    buffered: u32,
+}
+
+impl BitWriter {
+    pub fn new(order: BitOrder, bits: u32) -> Result<Self> {
+        BitWriter::with_capacity(0, order, bits)
+    }
+
+    pub fn with_capacity(capacity: usize, order: BitOrder, bits: u32) -> Result<Self> {
+        check_width(bits)?;
+        Ok(BitWriter {
+            bytes: Vec::with_capacity(capacity),
+            order,
+            bits,
+            buffer: 0,
+            buffered: 0,
+        })
+    }
+
+    // writes the low `bits` bits of symbol
+    pub fn write(&mut self, symbol: u32) {
+        self.write_bits(symbol, self.bits)
+    }
+
+    // bits is 1 to 32, checked when the writer was made or fewer than 8 for padding
+    fn write_bits(&mut self, symbol: u32, bits: u32) {
+        let symbol = symbol as u64 & mask(bits);
+
+        match self.order {
+            BitOrder::MsbFirst => {
+                self.buffer = self.buffer << bits | symbol;
+                self.buffered += bits;
+                while self.buffered >= 8 {
+                    self.buffered -= 8;
+                    self.bytes.push((self.buffer >> self.buffered) as u8);
+                }
+            }
+            BitOrder::LsbFirst => {
+                self.buffer |= symbol << self.buffered;
+                self.buffered += bits;
+                while self.buffered >= 8 {
+                    self.bytes.push(self.buffer as u8);
+                    self.buffer >>= 8;
+                    self.buffered -= 8;
+                }
+            }
+        }
+    }
+
+    // bits written since the last whole byte
+    pub fn pending_bits(&self) -> u32 {
+        self.buffered
+    }
+
+    // fills out the last byte with zero bits
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.buffered > 0 {
+            let padding = 8 - self.buffered;
+            self.write_bits(0, padding);
+        }
+        self.bytes
+    }
+}
+
+#[test]
+fn test_msb_first() -> Result<()> {
+    // "f" in base32 is "MY", 12 and 24
+    let mut reader = BitReader::new(b"f", BitOrder::MsbFirst, 5)?;
+    assert_eq!(reader.read(), Some(12));
+    assert_eq!(reader.bits_left(), 3);
+    assert_eq!(reader.read(), None);
+
+    let mut writer = BitWriter::new(BitOrder::MsbFirst, 5)?;
+    writer.write(12);
+    writer.write(24);
+    assert_eq!(writer.pending_bits(), 2);
+    assert_eq!(writer.finish(), b"f\0");
+    Ok(())
+}
+
+#[test]
+fn test_lsb_first() -> Result<()> {
+    let mut reader = BitReader::new(&[0b1010_1100, 0b0000_0001], BitOrder::LsbFirst, 3)?;
+    assert_eq!(reader.read(), Some(0b100));
+    assert_eq!(reader.read(), Some(0b101));
+    assert_eq!(reader.read(), Some(0b110));
+    assert_eq!(reader.read(), Some(0));
+    assert_eq!(reader.read(), Some(0));
+    assert_eq!(reader.read(), None);
+    assert_eq!(reader.bits_left(), 1);
+
+    let mut writer = BitWriter::new(BitOrder::LsbFirst, 3)?;
+    writer.write(0b100);
+    writer.write(0b101);
+    writer.write(0b110);
+    assert_eq!(writer.finish(), [0b1010_1100, 0b0000_0001]);
+    Ok(())
+}
+
+#[test]
+fn test_bad_widths() {
+    for &bits in &[0, 33] {
+        assert!(BitReader::new(b"f", BitOrder::MsbFirst, bits).is_err());
+        assert!(BitWriter::new(BitOrder::MsbFirst, bits).is_err());
+        assert!(BitWriter::with_capacity(1, BitOrder::LsbFirst, bits).is_err());
+    }
+}
+
+#[test]
+fn test_round_trip_every_width() -> Result<()> {
+    let bytes: Vec<u8> = (0..=255).collect();
+
+    for &order in &[BitOrder::MsbFirst, BitOrder::LsbFirst] {
+        for bits in 1..=32 {
+            let mut writer = BitWriter::new(order, bits)?;
+            let mut symbols = 0;
+            for symbol in BitReader::new(&bytes, order, bits)? {
+                assert!(symbol as u64 <= mask(bits));
+                writer.write(symbol);
+                symbols += 1;
+            }
+            assert_eq!(symbols, bytes.len() * 8 / bits as usize);
+
+            // the leftover bits weren't read, so they come back as zeroes
+            let out = writer.finish();
+            let whole_bytes = symbols * bits as usize / 8;
+            assert_eq!(
+                &out[..whole_bytes],
+                &bytes[..whole_bytes],
+                "{:?} {}",
+                order,
+                bits
+            );
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_symbols() -> Result<()> {
+    let symbols: Vec<u32> = BitReader::new(&[0xff, 0x00], BitOrder::MsbFirst, 6)?.collect();
+    assert_eq!(symbols, vec![0b11_1111, 0b11_0000]);
+    Ok(())
+}
@@ -0,0 +1,593 @@
+use super::discover::KNOWN_PREFIX;
+use super::layers::layer4::filter::Filter;
+pub use super::layers::LayerOptions;
+use super::layers::LAYERS;
+use anyhow::{anyhow, bail, ensure, Error, Result};
+use std::path::{Path, PathBuf};
+
+pub const USAGE: &str = "\
+usage: onion [peel] [<input>] [--out <dir>] [--from-layer <n>] [--to-layer <m>] [--stdout]
+                    [<layer options>]
+       onion decode-ascii85 [<file>]
+       onion layer <n> [<file>] [<layer options>]
+       onion build [<file>] [--to-layer <m>]
+       onion parity-report [<file>] [--pad]
+       onion discover [<file>] [--prefix <text>]
+       onion crib-drag <file> [--key-length <n>]
+       onion to-pcap [<file>] [--pcapng]
+       onion from-pcap [<file>] [--filter <expr>]
+
+commands:
+  peel             peel layers n to m of <input> (default input.txt), writing each document to
+                   <dir> (default out). --stdout writes only the last document, to stdout
+  decode-ascii85   decode the Ascii85 in <file> and write the bytes to stdout
+  layer            peel a single layer, given by number or name, and write the result to stdout
+  parity-report    list the bytes layer 2 drops from the payload in <file> for having bad parity.
+                   --pad finishes a short last group with zero bits instead of failing
+  discover         try layer 1 style transforms on the payload in <file> and list the ones that
+                   turn it into printable text starting with <text> (default \"==[ Layer\"), most
+                   english looking first
+  crib-drag        work out the layer 3 key for the payload in <file> by hand, by dragging
+                   guesses at the plaintext along it. commands are read from stdin, so <file>
+                   can't be -. the key is <n> bytes long, or as long as it looks
+  to-pcap          write the packets in the layer 4 payload in <file> to stdout as a pcap file,
+                   or pcapng with --pcapng, to look at in wireshark
+  from-pcap        peel layer 4 from a pcap or pcapng capture of the traffic in <file> instead
+                   of from the payload, and write the document to stdout. takes --filter
+  build            wrap the core in <file> in layers m (default 6) down to 0 and write the onion
+                   to stdout
+
+<file> and <input> can be - to read from stdin, which is also the default for <file>
+
+layer options:
+  --transform <ops>   peel layer 1 with <ops> instead of xor:0x55,ror:1. <ops> is a comma
+                      separated list of xor:N, rol:N, ror:N, add:N, sub:N, rev, swap and
+                      perm:76543210, applied to each byte in order
+  --crib <text>       give layer 3 some <text> that's in the document, to help it recover the key
+                      when there isn't much to go on. <text>@<offset> says where it is too
+  --key-length <n>    recover a layer 3 key <n> bytes long instead of guessing the length
+  --key <key>         decrypt layer 3 with a known key, in hex, or rolling:<start>:<step> for
+                      start, start + step, start + 2 * step and so on
+  --filter <expr>     make the layer 4 document from the packets that match <expr> instead of
+                      \"src 10.1.1.10 and dst 10.1.1.200 and dst port 42069 and valid-checksum\"
+
+filter expressions are like tcpdump's, made of
+  [src|dst] [host] <address>, [src|dst] net <address>/<prefix length>, [src|dst] port <port>,
+  valid-checksum (IP and UDP), valid-ip-checksum and valid-udp-checksum
+joined with and (&&), or (||), not (!) and brackets. and and or go left to right, like tcpdump
+
+exit codes:
+  0    success
+  64   bad command line
+  65   the input couldn't be decoded
+  74   couldn't read the input or write the output";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Peel {
+        input: PathBuf,
+        out: PathBuf,
+        from_layer: usize,
+        // inclusive
+        to_layer: usize,
+        stdout: bool,
+        options: LayerOptions,
+    },
+    DecodeAscii85 {
+        input: PathBuf,
+    },
+    Layer {
+        layer: usize,
+        input: PathBuf,
+        options: LayerOptions,
+    },
+    Build {
+        core: PathBuf,
+        to_layer: usize,
+    },
+    ParityReport {
+        input: PathBuf,
+        pad: bool,
+    },
+    Discover {
+        input: PathBuf,
+        prefix: String,
+    },
+    CribDrag {
+        input: PathBuf,
+        key_length: Option<usize>,
+    },
+    ToPcap {
+        input: PathBuf,
+        pcapng: bool,
+    },
+    FromPcap {
+        input: PathBuf,
+        filter: Option<Filter>,
+    },
+    Help,
+}
+
+// args doesn't include the program name
+pub fn parse(args: &[String]) -> Result<Command> {
+    let (command, rest) = match args.split_first() {
+        Some((first, rest)) if !first.starts_with('-') || first == "-" => (first.as_str(), rest),
+        _ => ("peel", args),
+    };
+
+    if rest.iter().any(|arg| arg == "-h" || arg == "--help") {
+        return Ok(Command::Help);
+    }
+
+    match command {
+        "help" => Ok(Command::Help),
+        "peel" => parse_peel(rest),
+        "decode-ascii85" => {
+            let input = parse_input(rest)?;
+            Ok(Command::DecodeAscii85 { input })
+        }
+        "layer" => {
+            let (layer, rest) = rest
+                .split_first()
+                .ok_or_else(|| anyhow!("layer needs a layer number or name"))?;
+            let layer = parse_layer(layer)?;
+            let (options, rest) = take_layer_options(rest)?;
+            let input = parse_input(&rest)?;
+            Ok(Command::Layer {
+                layer,
+                input,
+                options,
+            })
+        }
+        "build" => parse_build(rest),
+        "parity-report" => {
+            let pad = rest.iter().any(|arg| arg == "--pad");
+            let rest: Vec<String> = rest.iter().filter(|&arg| arg != "--pad").cloned().collect();
+            let input = parse_input(&rest)?;
+            Ok(Command::ParityReport { input, pad })
+        }
+        "discover" => parse_discover(rest),
+        "crib-drag" => parse_crib_drag(rest),
+        "to-pcap" => {
+            let pcapng = rest.iter().any(|arg| arg == "--pcapng");
+            let rest: Vec<String> = rest
+                .iter()
+                .filter(|&arg| arg != "--pcapng")
+                .cloned()
+                .collect();
+            let input = parse_input(&rest)?;
+            Ok(Command::ToPcap { input, pcapng })
+        }
+        "from-pcap" => parse_from_pcap(rest),
+        // `onion input.txt` is short for `onion peel input.txt`
+        _ => parse_peel(args),
+    }
+}
+
+fn parse_peel(args: &[String]) -> Result<Command> {
+    let mut input = None;
+    let mut out = PathBuf::from("out");
+    let mut from_layer = 0;
+    let mut to_layer = LAYERS.len() - 1;
+    let mut stdout = false;
+    let (options, args) = take_layer_options(args)?;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("{} needs a value", arg));
+        match arg.as_str() {
+            "--out" => out = PathBuf::from(value()?),
+            "--from-layer" => from_layer = parse_layer(value()?)?,
+            "--to-layer" => to_layer = parse_layer(value()?)?,
+            "--stdout" => stdout = true,
+            flag if flag.starts_with("--") => bail!("unknown option {}", flag),
+            _ if input.is_some() => bail!("unexpected argument {}", arg),
+            _ => input = Some(PathBuf::from(arg)),
+        }
+    }
+
+    ensure!(
+        from_layer <= to_layer,
+        "--from-layer {} is after --to-layer {}",
+        from_layer,
+        to_layer
+    );
+
+    Ok(Command::Peel {
+        input: input.unwrap_or_else(|| PathBuf::from("input.txt")),
+        out,
+        from_layer,
+        to_layer,
+        stdout,
+        options,
+    })
+}
+
+fn parse_build(args: &[String]) -> Result<Command> {
+    let mut rest = Vec::new();
+    let mut to_layer = LAYERS.len() - 1;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--to-layer" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| anyhow!("{} needs a value", arg))?;
+                to_layer = parse_layer(value)?;
+            }
+            _ => rest.push(arg.clone()),
+        }
+    }
+
+    let core = parse_input(&rest)?;
+    Ok(Command::Build { core, to_layer })
+}
+
+fn parse_discover(args: &[String]) -> Result<Command> {
+    let mut rest = Vec::new();
+    let mut prefix = String::from_utf8_lossy(KNOWN_PREFIX).into_owned();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--prefix" => {
+                prefix = args
+                    .next()
+                    .ok_or_else(|| anyhow!("{} needs a value", arg))?
+                    .clone();
+            }
+            _ => rest.push(arg.clone()),
+        }
+    }
+
+    let input = parse_input(&rest)?;
+    Ok(Command::Discover { input, prefix })
+}
+
+fn parse_crib_drag(args: &[String]) -> Result<Command> {
+    let (options, rest) = take_layer_options(args)?;
+    ensure!(
+        options.transform.is_none()
+            && options.cribs.is_empty()
+            && options.key.is_none()
+            && options.filter.is_none(),
+        "crib-drag only takes --key-length"
+    );
+
+    // stdin is for the commands
+    ensure!(!rest.is_empty(), "crib-drag needs a file");
+    let input = parse_input(&rest)?;
+    ensure!(
+        input.as_path() != Path::new("-"),
+        "crib-drag reads its commands from stdin, so the payload has to come from a file"
+    );
+    Ok(Command::CribDrag {
+        input,
+        key_length: options.key_length,
+    })
+}
+
+fn parse_from_pcap(args: &[String]) -> Result<Command> {
+    let (options, rest) = take_layer_options(args)?;
+    ensure!(
+        options.transform.is_none()
+            && options.cribs.is_empty()
+            && options.key_length.is_none()
+            && options.key.is_none(),
+        "from-pcap only takes --filter"
+    );
+    let input = parse_input(&rest)?;
+    Ok(Command::FromPcap {
+        input,
+        filter: options.filter,
+    })
+}
+
+fn parse_input(args: &[String]) -> Result<PathBuf> {
+    match args {
+        [] => Ok(PathBuf::from("-")),
+        [input] if !input.starts_with("--") => Ok(PathBuf::from(input)),
+        [input] => bail!("unknown option {}", input),
+        [_, unexpected, ..] => bail!("unexpected argument {}", unexpected),
+    }
+}
+
+// pulls the layer options out of args, leaving the rest
+fn take_layer_options(args: &[String]) -> Result<(LayerOptions, Vec<String>)> {
+    let mut options = LayerOptions::default();
+    let mut rest = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("{} needs a value", arg));
+        let bad = |e| anyhow!("bad {}: {}", arg, e);
+        match arg.as_str() {
+            "--transform" => options.transform = Some(value()?.parse().map_err(bad)?),
+            "--crib" => options.cribs.push(value()?.parse().map_err(bad)?),
+            "--key-length" => {
+                let length: usize = value()?.parse().map_err(|e| bad(Error::from(e)))?;
+                ensure!(length > 0, "--key-length can't be 0");
+                options.key_length = Some(length);
+            }
+            "--key" => options.key = Some(value()?.parse().map_err(bad)?),
+            "--filter" => options.filter = Some(value()?.parse().map_err(bad)?),
+            _ => rest.push(arg.clone()),
+        }
+    }
+
+    ensure!(
+        options.key.is_none() || (options.cribs.is_empty() && options.key_length.is_none()),
+        "--key is the whole key, it can't go with --crib or --key-length"
+    );
+    Ok((options, rest))
+}
+
+// a layer number, or one of the layer names
+fn parse_layer(arg: &str) -> Result<usize> {
+    let layer = match arg.parse::<usize>() {
+        Ok(layer) => layer,
+        Err(_) => LAYERS
+            .iter()
+            .position(|layer| layer.name() == arg)
+            .ok_or_else(|| anyhow!("there's no layer called {}", arg))?,
+    };
+
+    ensure!(
+        layer < LAYERS.len(),
+        "there are only layers 0 to {}, not {}",
+        LAYERS.len() - 1,
+        layer
+    );
+    Ok(layer)
+}
+
+#[cfg(test)]
+fn args(args: &str) -> Vec<String> {
+    args.split_whitespace().map(String::from).collect()
+}
+
+#[test]
+fn test_parse_peel() -> Result<()> {
+    let default = Command::Peel {
+        input: PathBuf::from("input.txt"),
+        out: PathBuf::from("out"),
+        from_layer: 0,
+        to_layer: LAYERS.len() - 1,
+        stdout: false,
+        options: LayerOptions::default(),
+    };
+    assert_eq!(parse(&args(""))?, default);
+    assert_eq!(parse(&args("peel"))?, default);
+    assert_eq!(parse(&args("input.txt"))?, default);
+
+    assert_eq!(
+        parse(&args(
+            "peel out/layer_2.txt --from-layer 2 --to-layer xor --stdout --out x"
+        ))?,
+        Command::Peel {
+            input: PathBuf::from("out/layer_2.txt"),
+            out: PathBuf::from("x"),
+            from_layer: 2,
+            to_layer: 3,
+            stdout: true,
+            options: LayerOptions::default(),
+        }
+    );
+    assert_eq!(
+        parse(&args("--stdout -"))?,
+        Command::Peel {
+            input: PathBuf::from("-"),
+            out: PathBuf::from("out"),
+            from_layer: 0,
+            to_layer: LAYERS.len() - 1,
+            stdout: true,
+            options: LayerOptions::default(),
+        }
+    );
+    assert_eq!(
+        parse(&args("--transform xor:0x55,ror:1 --to-layer 1"))?,
+        Command::Peel {
+            input: PathBuf::from("input.txt"),
+            out: PathBuf::from("out"),
+            from_layer: 0,
+            to_layer: 1,
+            stdout: false,
+            options: LayerOptions {
+                transform: Some("xor:0x55,ror:1".parse()?),
+                ..LayerOptions::default()
+            },
+        }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+use super::transform::{Op, Transform};
+#[cfg(test)]
+use super::xor::Keystream;
+
+#[test]
+fn test_parse_single_transforms() -> Result<()> {
+    assert_eq!(
+        parse(&args("decode-ascii85"))?,
+        Command::DecodeAscii85 {
+            input: PathBuf::from("-")
+        }
+    );
+    assert_eq!(
+        parse(&args("layer 4 out/layer_4.txt"))?,
+        Command::Layer {
+            layer: 4,
+            input: PathBuf::from("out/layer_4.txt"),
+            options: LayerOptions::default(),
+        }
+    );
+    assert_eq!(
+        parse(&args("layer parity"))?,
+        Command::Layer {
+            layer: 2,
+            input: PathBuf::from("-"),
+            options: LayerOptions::default(),
+        }
+    );
+    assert_eq!(
+        parse(&args("layer bitwise --transform rev,xor:7 out/layer_1.txt"))?,
+        Command::Layer {
+            layer: 1,
+            input: PathBuf::from("out/layer_1.txt"),
+            options: LayerOptions {
+                transform: Some(Transform::new(vec![Op::Reverse, Op::Xor(7)])),
+                ..LayerOptions::default()
+            },
+        }
+    );
+    assert_eq!(
+        parse(&args("layer xor --crib Traffic --crib ==[@0"))?,
+        Command::Layer {
+            layer: 3,
+            input: PathBuf::from("-"),
+            options: LayerOptions {
+                cribs: vec!["Traffic".parse()?, "==[@0".parse()?],
+                ..LayerOptions::default()
+            },
+        }
+    );
+    assert_eq!(
+        parse(&args("layer 3 --key-length 16"))?,
+        Command::Layer {
+            layer: 3,
+            input: PathBuf::from("-"),
+            options: LayerOptions {
+                key_length: Some(16),
+                ..LayerOptions::default()
+            },
+        }
+    );
+    assert_eq!(
+        parse(&args("layer 3 --key rolling:1:2"))?,
+        Command::Layer {
+            layer: 3,
+            input: PathBuf::from("-"),
+            options: LayerOptions {
+                key: Some(Keystream::Rolling { start: 1, step: 2 }),
+                ..LayerOptions::default()
+            },
+        }
+    );
+    assert_eq!(
+        parse(&args("build core.txt --to-layer 6"))?,
+        Command::Build {
+            core: PathBuf::from("core.txt"),
+            to_layer: 6
+        }
+    );
+    assert_eq!(
+        parse(&args("parity-report --pad out/layer_2.txt"))?,
+        Command::ParityReport {
+            input: PathBuf::from("out/layer_2.txt"),
+            pad: true
+        }
+    );
+    assert_eq!(
+        parse(&args("discover out/layer_1.txt"))?,
+        Command::Discover {
+            input: PathBuf::from("out/layer_1.txt"),
+            prefix: String::from("==[ Layer"),
+        }
+    );
+    assert_eq!(
+        parse(&args("discover --prefix ==["))?,
+        Command::Discover {
+            input: PathBuf::from("-"),
+            prefix: String::from("==["),
+        }
+    );
+    assert_eq!(
+        parse(&args("crib-drag out/layer_3.txt --key-length 32"))?,
+        Command::CribDrag {
+            input: PathBuf::from("out/layer_3.txt"),
+            key_length: Some(32),
+        }
+    );
+    assert_eq!(
+        parse(&args("to-pcap out/layer_4.txt --pcapng"))?,
+        Command::ToPcap {
+            input: PathBuf::from("out/layer_4.txt"),
+            pcapng: true,
+        }
+    );
+    assert_eq!(
+        parse(&args("from-pcap"))?,
+        Command::FromPcap {
+            input: PathBuf::from("-"),
+            filter: None,
+        }
+    );
+    assert_eq!(
+        parse(&[
+            String::from("from-pcap"),
+            String::from("--filter"),
+            String::from("not dst port 42069"),
+            String::from("capture.pcap"),
+        ])?,
+        Command::FromPcap {
+            input: PathBuf::from("capture.pcap"),
+            filter: Some("not dst port 42069".parse()?),
+        }
+    );
+    assert_eq!(
+        parse(&args("layer network --filter valid-checksum"))?,
+        Command::Layer {
+            layer: 4,
+            input: PathBuf::from("-"),
+            options: LayerOptions {
+                filter: Some("valid-checksum".parse()?),
+                ..LayerOptions::default()
+            },
+        }
+    );
+    assert_eq!(parse(&args("layer 1 --help"))?, Command::Help);
+    Ok(())
+}
+
+#[test]
+fn test_parse_errors() {
+    for bad in &[
+        "peel --out",
+        "peel --from-layer 3 --to-layer 2",
+        "peel --from-layer 99",
+        "peel a.txt b.txt",
+        "peel --verbose",
+        "layer",
+        "layer onion",
+        "layer 1 a.txt b.txt",
+        "decode-ascii85 --bare",
+        "build --to-layer",
+        "build a.txt --out b.txt",
+        "peel --transform",
+        "peel --transform flip:1",
+        "layer 1 --transform xor:0x100",
+        "peel --crib",
+        "layer 3 --crib @1",
+        "peel --key-length 0",
+        "peel --key-length x",
+        "layer 3 --key zz",
+        "layer 3 --key 00 --crib a",
+        "layer 3 --key 00 --key-length 1",
+        "discover --prefix",
+        "discover a.txt b.txt",
+        "crib-drag",
+        "crib-drag -",
+        "crib-drag a.txt --crib x",
+        "crib-drag a.txt --key-length 0",
+        "to-pcap --pcap",
+        "from-pcap a.pcap b.pcap",
+        "from-pcap --key 00",
+        "layer 4 --filter",
+        "layer 4 --filter colour",
+        "crib-drag a.txt --filter port",
+    ] {
+        assert!(parse(&args(bad)).is_err(), "{} should be rejected", bad);
+    }
+}
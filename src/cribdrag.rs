@@ -0,0 +1,354 @@
+// working out a repeating XOR key by hand, by sliding guesses at the plaintext along the
+// ciphertext and keeping the key bytes that look right
+use super::score;
+use super::xor;
+use anyhow::{anyhow, bail, ensure, Result};
+use std::io::{BufRead, Write};
+
+// shown in place of bytes under a key byte that isn't known yet
+const UNKNOWN: char = '·';
+// and bytes that don't decrypt to anything printable
+const UNPRINTABLE: char = '¤';
+
+pub struct CribDrag {
+    bytes: Vec<u8>,
+    key: Vec<Option<u8>>,
+}
+
+// the crib at one offset
+#[derive(Debug, PartialEq)]
+pub struct Drag {
+    pub offset: usize,
+    // the key bytes from the column under the first byte of the crib on
+    pub key: Vec<u8>,
+    // score::document_log_probabilities, averaged over everything the key bytes decrypt
+    pub score: f64,
+    // the crib's key bytes decrypting the same columns one key length further on
+    pub next: Vec<u8>,
+}
+
+fn render_byte(byte: Option<u8>) -> char {
+    match byte {
+        None => UNKNOWN,
+        Some(byte) if score::is_printable(byte) => byte as char,
+        Some(_) => UNPRINTABLE,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl CribDrag {
+    pub fn new(bytes: Vec<u8>, key_length: usize) -> Result<Self> {
+        ensure!(key_length > 0, "the key can't be empty");
+        ensure!(
+            bytes.len() >= key_length,
+            "there are only {} bytes, less than the key",
+            bytes.len()
+        );
+        Ok(CribDrag {
+            bytes,
+            key: vec![None; key_length],
+        })
+    }
+
+    pub fn key(&self) -> &[Option<u8>] {
+        &self.key
+    }
+
+    // the key bytes the crib implies at offset, None if they contradict each other
+    fn implied_key(&self, crib: &[u8], offset: usize) -> Option<Vec<(usize, u8)>> {
+        let cipher = self.bytes.get(offset..offset + crib.len())?;
+        let mut key: Vec<(usize, u8)> = Vec::with_capacity(crib.len());
+        for (i, (&cipher, &plain)) in cipher.iter().zip(crib).enumerate() {
+            let column = (offset + i) % self.key.len();
+            let byte = cipher ^ plain;
+            match key.iter().find(|&&(other, _)| other == column) {
+                Some(&(_, other)) if other != byte => return None,
+                Some(_) => {}
+                None => key.push((column, byte)),
+            }
+        }
+        Some(key)
+    }
+
+    // the crib at every offset where it fits, most likely first
+    pub fn drag(&self, crib: &[u8]) -> Vec<Drag> {
+        let table = score::document_log_probabilities();
+        let length = self.key.len();
+        let offsets = 0..(self.bytes.len() + 1).saturating_sub(crib.len());
+
+        let mut drags: Vec<Drag> = offsets
+            .filter_map(|offset| {
+                let key = self.implied_key(crib, offset)?;
+                let mut total = 0.0;
+                let mut count = 0;
+                for &(column, byte) in &key {
+                    for cipher in self.bytes.iter().skip(column).step_by(length) {
+                        total += table[(cipher ^ byte) as usize];
+                        count += 1;
+                    }
+                }
+                let next = (offset + length..offset + length + crib.len())
+                    .filter_map(|i| {
+                        Some(
+                            self.bytes.get(i)?
+                                ^ (self.bytes[i - length] ^ crib[i - length - offset]),
+                        )
+                    })
+                    .collect();
+                Some(Drag {
+                    offset,
+                    key: key.iter().map(|&(_, byte)| byte).collect(),
+                    score: total / count as f64,
+                    next,
+                })
+            })
+            .collect();
+        drags.sort_by(|a, b| b.score.total_cmp(&a.score));
+        drags
+    }
+
+    // keeps the key bytes the crib implies at offset
+    pub fn lock(&mut self, crib: &[u8], offset: usize) -> Result<()> {
+        let key = self
+            .implied_key(crib, offset)
+            .ok_or_else(|| anyhow!("the crib doesn't fit at offset {}", offset))?;
+        for (column, byte) in key {
+            self.key[column] = Some(byte);
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, column: usize, byte: Option<u8>) -> Result<()> {
+        let length = self.key.len();
+        let slot = self
+            .key
+            .get_mut(column)
+            .ok_or_else(|| anyhow!("the key only has columns 0 to {}", length - 1))?;
+        *slot = byte;
+        Ok(())
+    }
+
+    // fills in the key bytes that aren't known yet by frequency analysis
+    pub fn guess(&mut self) -> Result<()> {
+        let guess = xor::crack(&self.bytes, self.key.len(), &[])?;
+        for (known, guess) in self.key.iter_mut().zip(guess) {
+            known.get_or_insert(guess);
+        }
+        Ok(())
+    }
+
+    // the plaintext as far as the key is known
+    pub fn plaintext(&self) -> Vec<Option<u8>> {
+        self.bytes
+            .iter()
+            .zip(self.key.iter().cycle())
+            .map(|(byte, key)| key.map(|key| byte ^ key))
+            .collect()
+    }
+
+    // count lines of the plaintext from line first
+    pub fn render(&self, first: usize, count: usize) -> String {
+        let text: String = self.plaintext().into_iter().map(render_byte).collect();
+        text.lines()
+            .skip(first)
+            .take(count)
+            .map(|line| format!("{}\n", line))
+            .collect()
+    }
+
+    pub fn render_key(&self) -> String {
+        self.key
+            .iter()
+            .map(|byte| match byte {
+                Some(byte) => format!("{:02x}", byte),
+                None => String::from("__"),
+            })
+            .collect()
+    }
+}
+
+pub const HELP: &str = "\
+commands:
+  drag <text>            slide <text> along the ciphertext and list the best places for it, with
+                         the key bytes it needs there and what they decrypt one key length on
+  lock <offset> <text>   keep the key bytes <text> needs at <offset>
+  set <column> <byte>    set one key byte, in hex
+  unset <column>         forget one key byte
+  guess                  fill in the key bytes that aren't set by frequency analysis
+  clear                  forget the whole key
+  show [<line>] [<n>]    show <n> lines (default 20) of the plaintext from <line> (default 0)
+  key                    show the key, __ is a byte that isn't known
+  help                   show this
+  quit";
+
+// how many places drag lists
+const DRAGS: usize = 10;
+
+fn parse_number(arg: Option<&str>, default: usize) -> Result<usize> {
+    match arg {
+        None => Ok(default),
+        Some(arg) => arg.parse().map_err(|_| anyhow!("{} isn't a number", arg)),
+    }
+}
+
+// one command, false once it's time to stop
+fn command(state: &mut CribDrag, line: &str, output: &mut impl Write) -> Result<bool> {
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let mut args = rest.split_whitespace();
+
+    match command {
+        "" => {}
+        "drag" => {
+            ensure!(!rest.is_empty(), "drag needs some text");
+            for drag in state.drag(rest.as_bytes()).iter().take(DRAGS) {
+                let next: String = drag
+                    .next
+                    .iter()
+                    .map(|&byte| render_byte(Some(byte)))
+                    .collect();
+                writeln!(
+                    output,
+                    "{:>8}  {:>7.3}  {}  {:?}",
+                    drag.offset,
+                    drag.score,
+                    hex(&drag.key),
+                    next
+                )?;
+            }
+        }
+        "lock" => {
+            let (offset, text) = rest
+                .split_once(' ')
+                .ok_or_else(|| anyhow!("lock needs an offset and some text"))?;
+            state.lock(text.as_bytes(), parse_number(Some(offset), 0)?)?;
+            write!(output, "{}", state.render(0, 5))?;
+        }
+        "set" => {
+            let column = parse_number(args.next(), usize::MAX)?;
+            let byte = args.next().ok_or_else(|| anyhow!("set needs a key byte"))?;
+            let byte = u8::from_str_radix(byte, 16)
+                .map_err(|_| anyhow!("{} isn't a byte in hex", byte))?;
+            state.set(column, Some(byte))?;
+        }
+        "unset" => state.set(parse_number(args.next(), usize::MAX)?, None)?,
+        "guess" => state.guess()?,
+        "clear" => {
+            for column in 0..state.key().len() {
+                state.set(column, None)?;
+            }
+        }
+        "show" => {
+            let first = parse_number(args.next(), 0)?;
+            let count = parse_number(args.next(), 20)?;
+            write!(output, "{}", state.render(first, count))?;
+        }
+        "key" => writeln!(output, "{}", state.render_key())?,
+        "help" => writeln!(output, "{}", HELP)?,
+        "quit" | "exit" => return Ok(false),
+        _ => bail!("unknown command {}, try help", command),
+    }
+    Ok(true)
+}
+
+// reads commands from input until quit or the end, a bad command is reported and skipped
+pub fn run(state: &mut CribDrag, input: impl BufRead, mut output: impl Write) -> Result<()> {
+    writeln!(
+        output,
+        "{} bytes, a {} byte key. type help for the commands",
+        state.bytes.len(),
+        state.key.len()
+    )?;
+    write!(output, "> ")?;
+    output.flush()?;
+
+    for line in input.lines() {
+        let line = line?;
+        match command(state, line.trim_end(), &mut output) {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(e) => writeln!(output, "error: {}", e)?,
+        }
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+    writeln!(output)?;
+    Ok(())
+}
+
+#[cfg(test)]
+const DOCUMENT: &[u8] = b"==[ Layer 4/5: Network Traffic ]============================
+
+When computers send data over a network like the internet, the data is broken up placed
+within packets. As well as containing the data being sent, packets contain extra data like
+the destination address (where the packet should be sent to), and the source address.
+";
+
+#[test]
+fn test_drag() -> Result<()> {
+    let key = b"crib";
+    let cipher = xor::apply(DOCUMENT, key)?;
+    let mut state = CribDrag::new(cipher, key.len())?;
+
+    let drags = state.drag(b" the data ");
+    let offsets: Vec<usize> = drags.iter().map(|drag| drag.offset).collect();
+    // " the data " is in there twice
+    assert!(
+        offsets[..2].contains(&120) && offsets[..2].contains(&187),
+        "{:?}",
+        offsets
+    );
+    let drag = drags.iter().find(|drag| drag.offset == 120).unwrap();
+    assert_eq!(drag.next, &DOCUMENT[124..134]);
+
+    state.lock(b"==[ Layer", 0)?;
+    assert_eq!(
+        state.key(),
+        [Some(b'c'), Some(b'r'), Some(b'i'), Some(b'b')]
+    );
+    assert_eq!(
+        state.render(0, 1),
+        "==[ Layer 4/5: Network Traffic ]============================\n"
+    );
+
+    state.set(1, None)?;
+    assert_eq!(state.render_key(), "63__6962");
+    assert!(state.render(0, 1).starts_with("=·[ L·ye"));
+    assert!(state.set(4, Some(0)).is_err());
+
+    state.guess()?;
+    assert_eq!(
+        state.plaintext(),
+        DOCUMENT.iter().map(|&byte| Some(byte)).collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_run() -> Result<()> {
+    let cipher = xor::apply(DOCUMENT, b"crib")?;
+    let mut state = CribDrag::new(cipher, 4)?;
+    let commands =
+        "key\nlock 0 ==[ Layer\nset 3 ff\nunset 2\nkey\nbogus\nset 9 00\nshow 2 1\nquit\nkey\n";
+    let mut output = Vec::new();
+    run(&mut state, commands.as_bytes(), &mut output)?;
+
+    let output = String::from_utf8(output)?;
+    assert!(output.contains("> ________\n"), "{}", output);
+    assert!(output.contains("> 6372__ff\n"), "{}", output);
+    assert!(
+        output.contains("error: unknown command bogus"),
+        "{}",
+        output
+    );
+    assert!(
+        output.contains("error: the key only has columns 0 to 3"),
+        "{}",
+        output
+    );
+    // quit stops before the last key
+    assert!(output.ends_with("> "), "{}", output);
+    Ok(())
+}
@@ -0,0 +1,91 @@
+// finding the transform for a layer 1 style payload when the instructions are lost, from the bytes
+// every document is known to start with
+use super::score;
+use super::transform::{Op, Transform};
+use std::cmp::Ordering;
+
+pub const KNOWN_PREFIX: &[u8] = b"==[ Layer";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub transform: Transform,
+    // score::english of the whole output
+    pub score: f64,
+    pub output: Vec<u8>,
+}
+
+// every xor mask, then maybe a bit reversal, then every rotation. xor and rotation both spread
+// over each other, so this covers them applied in any order
+pub fn transforms() -> impl Iterator<Item = Transform> {
+    (0..=255u8).flat_map(|mask| {
+        [false, true].iter().flat_map(move |&reverse| {
+            (0..8).map(move |rotation| {
+                let mut ops = Vec::with_capacity(3);
+                if mask != 0 {
+                    ops.push(Op::Xor(mask));
+                }
+                if reverse {
+                    ops.push(Op::Reverse);
+                }
+                if rotation != 0 {
+                    ops.push(Op::RotateRight(rotation));
+                }
+                Transform::new(ops)
+            })
+        })
+    })
+}
+
+// the transforms that turn bytes into printable text starting with prefix, best first
+pub fn discover(bytes: &[u8], prefix: &[u8]) -> Vec<Candidate> {
+    if bytes.len() < prefix.len() {
+        return Vec::new();
+    }
+
+    let mut candidates: Vec<Candidate> = transforms()
+        .filter(|transform| transform.apply_all(&bytes[..prefix.len()]) == prefix)
+        .map(|transform| {
+            let output = transform.apply_all(bytes);
+            Candidate {
+                score: score::english(&output),
+                transform,
+                output,
+            }
+        })
+        .filter(|candidate| {
+            candidate
+                .output
+                .iter()
+                .all(|&byte| score::is_printable(byte))
+        })
+        .collect();
+
+    candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+    candidates
+}
+
+#[test]
+fn test_transforms() {
+    let transforms: Vec<Transform> = transforms().collect();
+    assert_eq!(transforms.len(), 256 * 2 * 8);
+    assert_eq!(transforms[0].ops(), []);
+    assert!(transforms.contains(&"xor:0x55,ror:1".parse().unwrap()));
+}
+
+#[test]
+fn test_discover() {
+    let document = b"==[ Layer 2/6: Parity Bit ]==\n\nMost of the bytes here have a parity bit.\n";
+    for transform in &["xor:0x55,ror:1", "rev,ror:3", "xor:0xa7"] {
+        let transform: Transform = transform.parse().unwrap();
+        let payload = transform.inverse().apply_all(document);
+
+        let candidates = discover(&payload, KNOWN_PREFIX);
+        assert_eq!(candidates[0].transform, transform);
+        assert_eq!(candidates[0].output, &document[..]);
+        for pair in candidates.windows(2) {
+            assert!(pair[0].score >= pair[1].score);
+        }
+    }
+
+    assert_eq!(discover(b"==[", KNOWN_PREFIX), []);
+}
@@ -1,6 +1,27 @@
-use super::super::ascii85::decode;
+use super::super::ascii85::{decode, encode};
+use super::Layer;
 use anyhow::Result;
 
-pub fn run(bytes: &[u8]) -> Result<Vec<u8>> {
-    decode(bytes)
+pub struct Layer0;
+
+impl Layer for Layer0 {
+    fn name(&self) -> &'static str {
+        "ascii85"
+    }
+
+    fn title(&self) -> &'static str {
+        "ASCII85"
+    }
+
+    fn description(&self) -> &'static str {
+        "Decode the Ascii85 payload"
+    }
+
+    fn peel(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        Ok(decode(bytes)?)
+    }
+
+    fn wrap(&self, document: &[u8]) -> Result<Vec<u8>> {
+        Ok(encode(document))
+    }
 }
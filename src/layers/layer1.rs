@@ -1,15 +1,19 @@
 use anyhow::Result;
 
-use super::super::ascii85::decode;
+use super::super::ascii85::{decode, encode};
+use super::super::transform::{Op, Transform};
+use super::{Layer, LayerOptions};
 
 pub fn flip_every_other_bit(n: u8) -> u8 {
-    let mask = 0b0101_0101;
-    n ^ mask
+    Op::Xor(0b0101_0101).apply(n)
 }
 
 pub fn rotate_right(n: u8) -> u8 {
-    let last_bit = n & 1;
-    (n >> 1) | (last_bit << 7)
+    Op::RotateRight(1).apply(n)
+}
+
+pub fn rotate_left(n: u8) -> u8 {
+    Op::RotateLeft(1).apply(n)
 }
 
 #[test]
@@ -28,10 +32,71 @@ fn test_rotate_right() {
     assert_eq!(0b1000_1000, rotate_right(0b0001_0001));
 }
 
-pub fn run(input: &[u8]) -> Result<Vec<u8>> {
-    let decoded = decode(input)?;
-    Ok(decoded
-        .iter()
-        .map(|&byte| rotate_right(flip_every_other_bit(byte)))
-        .collect())
+#[test]
+fn test_rotate_left() {
+    assert_eq!(0b0000_0001, rotate_left(0b1000_0000));
+    assert_eq!(0b0101_0101, rotate_left(0b1010_1010));
+    for n in 0..=255 {
+        assert_eq!(n, rotate_right(rotate_left(n)));
+    }
+}
+
+pub struct Layer1 {
+    transform: Transform,
+}
+
+impl Layer1 {
+    // flip every other bit, then rotate one bit to the right
+    pub const DEFAULT: Layer1 = Layer1::new(Transform::from_static(&[
+        Op::Xor(0b0101_0101),
+        Op::RotateRight(1),
+    ]));
+
+    pub const fn new(transform: Transform) -> Self {
+        Layer1 { transform }
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+}
+
+#[test]
+fn test_default_transform() {
+    let transform = Layer1::DEFAULT.transform;
+    assert_eq!(transform.to_string(), "xor:0x55,ror:1");
+    assert_eq!(transform.apply(0b1010_1010), 0b1111_1111);
+    assert_eq!(transform.apply(0b0101_0100), 0b1000_0000);
+    assert_eq!(transform.apply(0b0101_0101), 0b0000_0000);
+    for n in 0..=255 {
+        assert_eq!(n, transform.inverse().apply(transform.apply(n)));
+    }
+}
+
+impl Layer for Layer1 {
+    fn name(&self) -> &'static str {
+        "bitwise"
+    }
+
+    fn title(&self) -> &'static str {
+        "Bitwise Operations"
+    }
+
+    fn description(&self) -> &'static str {
+        "Flip every second bit, then rotate each byte one bit to the right"
+    }
+
+    fn peel(&self, input: &[u8]) -> Result<Vec<u8>> {
+        let decoded = decode(input)?;
+        Ok(self.transform.apply_all(&decoded))
+    }
+
+    fn wrap(&self, document: &[u8]) -> Result<Vec<u8>> {
+        Ok(encode(&self.transform.inverse().apply_all(document)))
+    }
+
+    fn with_options(&self, options: &LayerOptions) -> Option<Box<dyn Layer>> {
+        let transform = options.transform.clone()?;
+        Some(Box::new(Layer1::new(transform)))
+    }
 }
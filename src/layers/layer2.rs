@@ -1,94 +1,98 @@
-use anyhow::{ensure, Result};
-use std::io::{Error, ErrorKind::InvalidInput};
+use super::super::ascii85;
+use super::super::parity::{
+    add_parity, strip_parity, strip_parity_with_report, BitPosition, Parity, ParityReport,
+    ParityScheme, StripOptions,
+};
+use super::{random_bytes, Layer};
+use anyhow::Result;
 
-fn count_ones(n: u8) -> u8 {
-    let mut n = n;
-    let mut count = 0;
-    for _ in 0..7 {
-        n >>= 1;
-        if n & 1 == 1 {
-            count += 1;
-        }
-    }
-    count
-}
+// 7 data bits then the parity bit, which makes the number of ones even
+pub const PARITY: ParityScheme = ParityScheme {
+    parity: Parity::Even,
+    position: BitPosition::Lsb,
+    data_bits: 7,
+};
 
-fn parity(n: u8) -> bool {
-    count_ones(n) % 2 == 1
+#[test]
+fn test_correct_parity() {
+    assert!(PARITY.check(0b1011_0010));
+    assert!(PARITY.check(0b0000_0000));
+    assert!(PARITY.check(0b1111_1111));
+    assert!(!PARITY.check(0b1111_1110));
 }
 
-fn get_parity_bit(n: u8) -> bool {
-    (n & 1) == 1
+pub fn combine(bytes: &[u8]) -> Result<Vec<u8>> {
+    strip_parity(bytes, PARITY)
 }
 
-fn correct_parity(n: u8) -> bool {
-    parity(n) == get_parity_bit(n)
+// the same, but says which bytes were dropped
+pub fn combine_with_report(bytes: &[u8], options: StripOptions) -> Result<(Vec<u8>, ParityReport)> {
+    strip_parity_with_report(bytes, PARITY, options)
 }
 
-#[test]
-fn test_count_ones() {
-    assert_eq!(0, count_ones(0b0000_0000));
-    assert_eq!(1, count_ones(0b0000_0010));
-    assert_eq!(1, count_ones(0b0100_0000));
-    assert_eq!(7, count_ones(0b1111_1110));
+// the other direction. there's no way to say how long the data is, so it's padded to a multiple
+// of 7 with newlines
+fn spread(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut padded = bytes.to_vec();
+    while !padded.len().is_multiple_of(7) {
+        padded.push(b'\n');
+    }
+    add_parity(&padded, PARITY)
 }
 
 #[test]
-fn test_parity() {
-    assert_eq!(0, parity(0b0000_0000) as u8);
-    assert_eq!(1, parity(0b0000_0010) as u8);
-    assert_eq!(0, parity(0b0010_0010) as u8);
-    assert_eq!(1, parity(0b0100_0000) as u8);
-    assert_eq!(1, parity(0b1111_1110) as u8);
-}
+fn test_spread() -> Result<()> {
+    let bytes = b"parity!";
+    let encoded = spread(bytes)?;
+    assert_eq!(encoded.len(), 8);
+    assert!(encoded.iter().all(|&b| PARITY.check(b as u32)));
+    assert_eq!(combine(&encoded)?, bytes);
 
-#[test]
-fn test_correct_parity() {
-    assert!(correct_parity(0b1011_0010));
-    assert!(correct_parity(0b0000_0000));
-    assert!(correct_parity(0b1111_1111));
+    // padded
+    assert_eq!(combine(&spread(b"odd")?)?, b"odd\n\n\n\n");
+    Ok(())
 }
 
-fn combine(bytes: &[u8]) -> Result<Vec<u8>> {
-    let good_bytes: Vec<u8> = bytes
-        .iter()
-        .copied()
-        .filter(|&b| correct_parity(b))
-        .collect();
+pub struct Layer2;
 
-    ensure!(
-        good_bytes.len() % 8 == 0,
-        Error::new(InvalidInput, "input needs to be a multiple of 8 bytes")
-    );
+impl Layer for Layer2 {
+    fn name(&self) -> &'static str {
+        "parity"
+    }
 
-    Ok(good_bytes
-        .as_slice()
-        .chunks(8)
-        .map(|bytes| {
-            // drop parity bit
-            let bytes: Vec<u8> = bytes.iter().map(|b| b >> 1).collect();
+    fn title(&self) -> &'static str {
+        "Parity Bit"
+    }
 
-            // line up the bits 7 at a time
-            let mut temp: u64 = 0;
-            for (i, &byte) in bytes.iter().enumerate().take(8) {
-                let shift = 7 * (7 - i);
-                temp += ((byte as u64) << shift) as u64;
-            }
+    fn description(&self) -> &'static str {
+        "Drop bytes with bad parity, then pack the remaining 7 bit values into bytes"
+    }
 
-            let mut out: Vec<u8> = Vec::new();
-            // read off 7 bytes
-            for _ in 0..7 {
-                out.insert(0, (temp & 0b1111_1111) as u8); //todo this feels bad
-                temp >>= 8;
-            }
+    fn peel(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let decoded = ascii85::decode(bytes)?;
+        combine(&decoded)
+    }
 
-            out
-        })
-        .flatten()
-        .collect())
+    fn wrap(&self, document: &[u8]) -> Result<Vec<u8>> {
+        let spread = spread(document)?;
+        let noise = random_bytes(spread.len() * 2)?;
+
+        // slip in a byte with the wrong parity before roughly one in eight of the good ones
+        let mut bytes = Vec::with_capacity(spread.len() * 9 / 8);
+        for (&byte, noise) in spread.iter().zip(noise.chunks(2)) {
+            if noise[0] < 32 {
+                bytes.push(noise[1] ^ PARITY.check(noise[1] as u32) as u8);
+            }
+            bytes.push(byte);
+        }
+        Ok(ascii85::encode(&bytes))
+    }
 }
 
-pub fn run(bytes: &[u8]) -> Result<Vec<u8>> {
-    let decoded = super::super::ascii85::decode(bytes)?;
-    combine(&decoded)
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_combine_arbitrary(bytes in proptest::collection::vec(0..=255u8, 0..256)) {
+        let _ = combine(&bytes);
+    }
 }
@@ -1,36 +1,138 @@
 use super::super::ascii85;
-use anyhow::Result;
-use std::convert::TryInto;
-
-fn decrypt(bytes: &[u8], key: &[u8; 32]) -> Result<Vec<u8>> {
-    Ok(key
-        .iter()
-        .cycle()
-        .zip(bytes)
-        .map(|(key, byte)| byte ^ key)
-        .collect())
+use super::super::xor::{self, Crib, Keystream};
+use super::{random_bytes, Layer, LayerOptions};
+use anyhow::{anyhow, Result};
+
+// the longest key to look for when the length isn't given
+pub const MAX_KEY_LENGTH: usize = 64;
+
+// where layer 3 gets its key from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Key {
+    // recover a repeating key by frequency analysis, guessing the length if it isn't given.
+    // cribs are plaintext known to be in the document
+    Crack {
+        length: Option<usize>,
+        cribs: Vec<Crib>,
+    },
+    // the key is already known
+    Known(Keystream),
 }
 
-fn key(bytes: &[u8]) -> Result<[u8; 32]> {
-    let cipher_bytes: Vec<u8> = bytes[0..32].to_vec();
+pub struct Layer3 {
+    key: Key,
+}
 
-    // found this by first grabbing the last 32 bytes of the first line and hoping they were all '='
-    // unfortunately, this approach is 2 characters shy, but it was enough to guess the correct header
-    let known_string: &[u8; 32] = b"==[ Layer 4/5: Network Traffic ]";
+impl Layer3 {
+    pub const DEFAULT: Layer3 = Layer3::new(Key::Crack {
+        length: None,
+        cribs: Vec::new(),
+    });
 
-    let key_bytes: Vec<u8> = cipher_bytes
-        .into_iter()
-        .zip(known_string)
-        .map(|(cipher, &known)| cipher ^ known)
-        .collect();
+    pub const fn new(key: Key) -> Self {
+        Layer3 { key }
+    }
 
-    let key: [u8; 32] = key_bytes.as_slice().try_into()?;
+    // the keystream the decoded bytes were encrypted with
+    pub fn keystream(&self, bytes: &[u8]) -> Result<Keystream> {
+        match &self.key {
+            Key::Known(keystream) => Ok(keystream.clone()),
+            Key::Crack { length, cribs } => {
+                let length = match length {
+                    Some(length) => *length,
+                    None => xor::guess_key_length(bytes, MAX_KEY_LENGTH).ok_or_else(|| {
+                        anyhow!("there isn't enough to guess the key length from")
+                    })?,
+                };
+                let key = xor::crack(bytes, length, cribs)?;
+                // the guess can be a multiple of the real length, then the key nearly repeats
+                // and cracking again at the real length gets the bytes that didn't right
+                let period = xor::likely_period(&key);
+                if period < key.len() {
+                    return Ok(Keystream::Repeating(xor::crack(bytes, period, cribs)?));
+                }
+                Ok(Keystream::Repeating(key))
+            }
+        }
+    }
 
-    Ok(key)
+    // the document, and the keystream that it was decrypted with
+    pub fn peel_with_key(&self, bytes: &[u8]) -> Result<(Vec<u8>, Keystream)> {
+        let decoded = ascii85::decode(bytes)?;
+        let keystream = self.keystream(&decoded)?;
+        Ok((keystream.apply(&decoded)?, keystream))
+    }
 }
 
-pub fn run(bytes: &[u8]) -> Result<Vec<u8>> {
-    let decoded = ascii85::decode(bytes)?;
-    let key = key(&decoded)?;
-    decrypt(&decoded, &key)
+impl Layer for Layer3 {
+    fn name(&self) -> &'static str {
+        "xor"
+    }
+
+    fn title(&self) -> &'static str {
+        "XOR Encryption"
+    }
+
+    fn description(&self) -> &'static str {
+        "Decrypt a repeating XOR key recovered by frequency analysis"
+    }
+
+    fn peel(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        self.peel_with_key(bytes).map(|(document, _)| document)
+    }
+
+    // there's no other way to get the key if it was recovered
+    fn peel_with_diagnostics(&self, bytes: &[u8]) -> Result<(Vec<u8>, Vec<String>)> {
+        let (document, keystream) = self.peel_with_key(bytes)?;
+        Ok((document, vec![format!("key {}", keystream)]))
+    }
+
+    // with a random key unless there's a known one
+    fn wrap(&self, document: &[u8]) -> Result<Vec<u8>> {
+        let keystream = match &self.key {
+            Key::Known(keystream) => keystream.clone(),
+            Key::Crack { length, .. } => Keystream::Repeating(random_bytes(length.unwrap_or(32))?),
+        };
+        Ok(ascii85::encode(&keystream.apply(document)?))
+    }
+
+    fn with_options(&self, options: &LayerOptions) -> Option<Box<dyn Layer>> {
+        let key = match &options.key {
+            Some(keystream) => Key::Known(keystream.clone()),
+            None if options.key_length.is_some() || !options.cribs.is_empty() => Key::Crack {
+                length: options.key_length,
+                cribs: options.cribs.clone(),
+            },
+            None => return None,
+        };
+        Some(Box::new(Layer3::new(key)))
+    }
+}
+
+#[test]
+fn test_key_lengths() -> Result<()> {
+    let mut layer4 = format!(
+        "{:=<60}\n\n{}\n\n",
+        "==[ Layer 4/6: Network Traffic ]",
+        super::XOR_TEXT
+    )
+    .into_bytes();
+    let payload: Vec<u8> = (0..=255).cycle().take(600).collect();
+    layer4.extend_from_slice(&ascii85::encode(&payload));
+
+    for key in &[
+        "5a",
+        "1337c0de",
+        "0f1e2d3c4b5a69788796a5b4c3d2e1f00f1e2d3c4b5a6978",
+    ] {
+        let keystream: Keystream = key.parse()?;
+        let payload = Layer3::new(Key::Known(keystream.clone())).wrap(&layer4)?;
+        let (peeled, recovered) = Layer3::DEFAULT.peel_with_key(&payload)?;
+        assert_eq!(peeled, layer4);
+        assert_eq!(recovered, keystream);
+    }
+
+    let rolling = Layer3::new(Key::Known("rolling:7:13".parse()?));
+    assert_eq!(rolling.peel(&rolling.wrap(&layer4)?)?, layer4);
+    Ok(())
 }
@@ -0,0 +1,450 @@
+// packet filters written like `src 10.1.1.10 and dst port 42069 and valid-checksum`, in the
+// spirit of tcpdump's. like tcpdump, `and` and `or` are equally strong and go left to right, so
+// `a or b and c` is `(a or b) and c`
+use super::{UdpPacket, DESTINATION, DESTINATION_PORT, SOURCE};
+use anyhow::{anyhow, bail, ensure, Error, Result};
+use std::borrow::Cow;
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+// which end of the packet an address or port is checked against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    // src
+    Source,
+    // dst
+    Destination,
+    // neither, either end will do
+    Either,
+}
+
+impl Direction {
+    fn check<T: Copy>(self, source: T, destination: T, test: impl Fn(T) -> bool) -> bool {
+        match self {
+            Direction::Source => test(source),
+            Direction::Destination => test(destination),
+            Direction::Either => test(source) || test(destination),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Primitive {
+    // [src|dst] [host] 10.1.1.10
+    Host(Direction, Ipv4Addr),
+    // [src|dst] net 10.1.1.0/24
+    Net(Direction, Ipv4Addr, u8),
+    // [src|dst] port 42069
+    Port(Direction, u16),
+    // valid-checksum, the IP and UDP checksums both
+    ValidChecksum,
+    // valid-ip-checksum
+    ValidIpChecksum,
+    // valid-udp-checksum
+    ValidUdpChecksum,
+}
+
+fn netmask(prefix: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0)
+}
+
+impl Primitive {
+    pub fn matches(&self, packet: &UdpPacket) -> bool {
+        let ip = &packet.ip_header;
+        let udp = &packet.udp_header;
+        match *self {
+            Primitive::Host(direction, address) => {
+                direction.check(ip.source, ip.destination, |a| a == address)
+            }
+            Primitive::Net(direction, network, prefix) => {
+                let mask = netmask(prefix);
+                direction.check(ip.source, ip.destination, |a| {
+                    u32::from(a) & mask == u32::from(network)
+                })
+            }
+            Primitive::Port(direction, port) => {
+                direction.check(udp.source_port, udp.destination_port, |p| p == port)
+            }
+            Primitive::ValidChecksum => packet.valid_checksums(),
+            Primitive::ValidIpChecksum => packet.valid_ip_checksum(),
+            Primitive::ValidUdpChecksum => packet.valid_udp_checksum(),
+        }
+    }
+}
+
+impl fmt::Display for Primitive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = |direction| match direction {
+            Direction::Source => "src ",
+            Direction::Destination => "dst ",
+            Direction::Either => "",
+        };
+        match *self {
+            Primitive::Host(Direction::Either, address) => write!(f, "host {}", address),
+            Primitive::Host(d, address) => write!(f, "{}{}", direction(d), address),
+            Primitive::Net(d, network, prefix) => {
+                write!(f, "{}net {}/{}", direction(d), network, prefix)
+            }
+            Primitive::Port(d, port) => write!(f, "{}port {}", direction(d), port),
+            Primitive::ValidChecksum => write!(f, "valid-checksum"),
+            Primitive::ValidIpChecksum => write!(f, "valid-ip-checksum"),
+            Primitive::ValidUdpChecksum => write!(f, "valid-udp-checksum"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    // pushes whether the packet matches
+    Test(Primitive),
+    // the rest pop their operands and push the result
+    Not,
+    And,
+    Or,
+}
+
+// a filter as a program for a little stack machine, each operator after its operands. an empty
+// one lets everything through
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    program: Cow<'static, [Instruction]>,
+}
+
+impl Filter {
+    // the packets the layer 4 document is made of
+    pub const DEFAULT: Filter = Filter::from_static(&[
+        Instruction::Test(Primitive::Host(Direction::Source, SOURCE)),
+        Instruction::Test(Primitive::Host(Direction::Destination, DESTINATION)),
+        Instruction::And,
+        Instruction::Test(Primitive::Port(Direction::Destination, DESTINATION_PORT)),
+        Instruction::And,
+        Instruction::Test(Primitive::ValidChecksum),
+        Instruction::And,
+    ]);
+
+    // the program has to leave exactly one result on the stack, or none at all
+    const fn from_static(program: &'static [Instruction]) -> Self {
+        Filter {
+            program: Cow::Borrowed(program),
+        }
+    }
+
+    pub fn program(&self) -> &[Instruction] {
+        &self.program
+    }
+
+    pub fn matches(&self, packet: &UdpPacket) -> bool {
+        let mut stack = Vec::new();
+        for instruction in self.program.iter() {
+            let result = match instruction {
+                Instruction::Test(primitive) => primitive.matches(packet),
+                Instruction::Not => !stack.pop().unwrap_or(false),
+                Instruction::And => stack.pop().unwrap_or(false) & stack.pop().unwrap_or(false),
+                Instruction::Or => stack.pop().unwrap_or(false) | stack.pop().unwrap_or(false),
+            };
+            stack.push(result);
+        }
+        stack.pop().unwrap_or(true)
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::DEFAULT
+    }
+}
+
+// whitespace separates words, brackets and ! don't need it
+fn tokenize(s: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in s.char_indices() {
+        let single = matches!(c, '(' | ')' | '!');
+        if c.is_whitespace() || single {
+            if let Some(start) = start.take() {
+                tokens.push(&s[start..i]);
+            }
+            if single {
+                tokens.push(&s[i..i + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(start) = start {
+        tokens.push(&s[start..]);
+    }
+    tokens
+}
+
+struct Parser<'a> {
+    tokens: std::iter::Peekable<std::vec::IntoIter<&'a str>>,
+    program: Vec<Instruction>,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self, after: &str) -> Result<&'a str> {
+        self.tokens
+            .next()
+            .ok_or_else(|| anyhow!("the filter ends after {}", after))
+    }
+
+    // primitives and negations joined with and or or
+    fn expression(&mut self, after: &str) -> Result<()> {
+        self.negation(after)?;
+        while let Some(&token) = self.tokens.peek() {
+            let instruction = match token {
+                "and" | "&&" => Instruction::And,
+                "or" | "||" => Instruction::Or,
+                _ => break,
+            };
+            self.tokens.next();
+            self.negation(token)?;
+            self.program.push(instruction);
+        }
+        Ok(())
+    }
+
+    fn negation(&mut self, after: &str) -> Result<()> {
+        match self.next(after)? {
+            token @ ("not" | "!") => {
+                self.negation(token)?;
+                self.program.push(Instruction::Not);
+            }
+            "(" => {
+                self.expression("(")?;
+                match self.tokens.next() {
+                    Some(")") => {}
+                    Some(token) => bail!("expected ) in the filter, not {}", token),
+                    None => bail!("the filter is missing a )"),
+                }
+            }
+            token => {
+                let primitive = self.primitive(token)?;
+                self.program.push(Instruction::Test(primitive));
+            }
+        }
+        Ok(())
+    }
+
+    fn primitive(&mut self, token: &'a str) -> Result<Primitive> {
+        let (direction, token) = match token {
+            "src" => (Direction::Source, self.next(token)?),
+            "dst" => (Direction::Destination, self.next(token)?),
+            _ => (Direction::Either, token),
+        };
+
+        let primitive = match token {
+            "host" => Primitive::Host(direction, parse_address(self.next(token)?)?),
+            "net" => {
+                let (network, prefix) = parse_network(self.next(token)?)?;
+                Primitive::Net(direction, network, prefix)
+            }
+            "port" => {
+                let port = self.next(token)?;
+                let port = port
+                    .parse()
+                    .map_err(|_| anyhow!("{} isn't a port number", port))?;
+                Primitive::Port(direction, port)
+            }
+            "valid-checksum" | "valid-ip-checksum" | "valid-udp-checksum"
+                if direction != Direction::Either =>
+            {
+                bail!("checksums don't have a src or dst")
+            }
+            "valid-checksum" => Primitive::ValidChecksum,
+            "valid-ip-checksum" => Primitive::ValidIpChecksum,
+            "valid-udp-checksum" => Primitive::ValidUdpChecksum,
+            _ => match token.parse() {
+                Ok(address) => Primitive::Host(direction, address),
+                Err(_) if direction == Direction::Either => {
+                    bail!("{} isn't something a filter can check", token)
+                }
+                Err(_) => bail!(
+                    "expected an address, host, net or port after src or dst, not {}",
+                    token
+                ),
+            },
+        };
+        Ok(primitive)
+    }
+}
+
+fn parse_address(s: &str) -> Result<Ipv4Addr> {
+    s.parse()
+        .map_err(|_| anyhow!("{} isn't an IPv4 address", s))
+}
+
+// 10.1.1.0/24
+fn parse_network(s: &str) -> Result<(Ipv4Addr, u8)> {
+    let (network, prefix) = s
+        .split_once('/')
+        .ok_or_else(|| anyhow!("{} needs a prefix length, like {}/24", s, s))?;
+    let network = parse_address(network)?;
+    let prefix: u8 = prefix
+        .parse()
+        .ok()
+        .filter(|&prefix| prefix <= 32)
+        .ok_or_else(|| anyhow!("{} isn't a prefix length", prefix))?;
+    ensure!(
+        u32::from(network) & !netmask(prefix) == 0,
+        "{} has bits set past the first {}",
+        network,
+        prefix
+    );
+    Ok((network, prefix))
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Filter> {
+        let mut parser = Parser {
+            tokens: tokenize(s).into_iter().peekable(),
+            program: Vec::new(),
+        };
+        if parser.tokens.peek().is_some() {
+            parser.expression("the start")?;
+        }
+        if let Some(token) = parser.tokens.next() {
+            bail!("unexpected {} in the filter", token);
+        }
+        Ok(Filter {
+            program: Cow::Owned(parser.program),
+        })
+    }
+}
+
+// the shortest way to write it that parses back the same
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // each piece so far, and whether it's an and or an or that needs brackets to be an operand
+        let mut stack: Vec<(String, bool)> = Vec::new();
+        let operand = |stack: &mut Vec<(String, bool)>| match stack.pop() {
+            Some((text, true)) => format!("({})", text),
+            Some((text, false)) => text,
+            None => String::new(),
+        };
+
+        for instruction in self.program.iter() {
+            let piece = match instruction {
+                Instruction::Test(primitive) => (primitive.to_string(), false),
+                Instruction::Not => (format!("not {}", operand(&mut stack)), false),
+                Instruction::And | Instruction::Or => {
+                    // left to right, so the left operand never needs brackets
+                    let right = operand(&mut stack);
+                    let left = stack.pop().map(|(text, _)| text).unwrap_or_default();
+                    let op = match instruction {
+                        Instruction::And => "and",
+                        _ => "or",
+                    };
+                    (format!("{} {} {}", left, op, right), true)
+                }
+            };
+            stack.push(piece);
+        }
+        match stack.pop() {
+            Some((text, _)) => write!(f, "{}", text),
+            None => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn test_parse() -> Result<()> {
+    let default = "src 10.1.1.10 and dst 10.1.1.200 and dst port 42069 and valid-checksum";
+    assert_eq!(default.parse::<Filter>()?, Filter::DEFAULT);
+    assert_eq!(Filter::DEFAULT.to_string(), default);
+    assert_eq!(
+        "src host 10.1.1.10 && dst 10.1.1.200 && dst port 42069 && valid-checksum"
+            .parse::<Filter>()?,
+        Filter::DEFAULT
+    );
+
+    // and and or go left to right
+    let filter: Filter = "port 1 or port 2 and !valid-udp-checksum".parse()?;
+    assert_eq!(
+        filter.program(),
+        [
+            Instruction::Test(Primitive::Port(Direction::Either, 1)),
+            Instruction::Test(Primitive::Port(Direction::Either, 2)),
+            Instruction::Or,
+            Instruction::Test(Primitive::ValidUdpChecksum),
+            Instruction::Not,
+            Instruction::And,
+        ]
+    );
+    assert_eq!(
+        filter.to_string(),
+        "port 1 or port 2 and not valid-udp-checksum"
+    );
+
+    for text in &[
+        "host 10.0.0.1 and (src net 10.0.0.0/8 or not (dst port 53 or valid-ip-checksum))",
+        "not (port 1 and port 2)",
+        "",
+    ] {
+        assert_eq!(text.parse::<Filter>()?.to_string(), *text);
+    }
+    assert_eq!("10.0.0.1".parse::<Filter>()?.to_string(), "host 10.0.0.1");
+    Ok(())
+}
+
+#[test]
+fn test_parse_errors() {
+    for bad in &[
+        "src",
+        "src port",
+        "src port x",
+        "dst port 65536",
+        "host 10.0.0",
+        "net 10.0.0.0",
+        "net 10.0.0.1/24",
+        "net 10.0.0.0/33",
+        "src valid-checksum",
+        "src colour",
+        "colour",
+        "port 1 and",
+        "and port 1",
+        "(port 1",
+        "port 1)",
+        "port 1 port 2",
+        "not",
+    ] {
+        assert!(bad.parse::<Filter>().is_err(), "{} should be rejected", bad);
+    }
+}
+
+#[test]
+fn test_matches() -> Result<()> {
+    use std::net::SocketAddrV4;
+    let source = SocketAddrV4::new(SOURCE, 1234);
+    let destination = SocketAddrV4::new(DESTINATION, DESTINATION_PORT);
+    let mut bytes = super::udp_datagram(source, destination, 1, b"good")?;
+    let mut corrupt = super::udp_datagram(destination, source, 2, b"bad")?;
+    corrupt[28] ^= 1;
+    bytes.extend_from_slice(&corrupt);
+    let (packets, _) = super::parse_packets(&bytes);
+    let matching = |filter: &str| -> Result<Vec<bool>> {
+        let filter: Filter = filter.parse()?;
+        Ok(packets
+            .iter()
+            .map(|packet| filter.matches(packet))
+            .collect())
+    };
+
+    assert_eq!(matching("")?, [true, true]);
+    assert_eq!(matching(&Filter::DEFAULT.to_string())?, [true, false]);
+    assert_eq!(matching("host 10.1.1.10 and port 1234")?, [true, true]);
+    assert_eq!(matching("dst 10.1.1.10")?, [false, true]);
+    assert_eq!(matching("src net 10.1.1.192/26")?, [false, true]);
+    assert_eq!(matching("net 0.0.0.0/0")?, [true, true]);
+    assert_eq!(
+        matching("src port 42069 or valid-ip-checksum")?,
+        [true, true]
+    );
+    assert_eq!(matching("not valid-udp-checksum")?, [false, true]);
+    assert_eq!(matching("not (valid-checksum or port 1)")?, [false, true]);
+    Ok(())
+}
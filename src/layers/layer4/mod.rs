@@ -0,0 +1,1326 @@
+pub mod filter;
+pub mod pcap;
+pub mod reassembly;
+
+use super::super::ascii85::{decode, encode};
+use super::{random_bytes, Layer, LayerOptions};
+use anyhow::{anyhow, ensure, Result};
+use filter::Filter;
+use pcap::CapturedPacket;
+use reassembly::Reassembler;
+use std::convert::TryInto;
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::Duration;
+
+fn read_as_u16(bytes: &[u8]) -> Result<Vec<u16>> {
+    let words = bytes
+        .chunks_exact(2)
+        .map(|chunk| {
+            let word: Result<[u8; 2]> = chunk
+                .try_into()
+                .map_err(|_| anyhow!("couldn't fit {} bytes into 16 bit word", chunk.len()));
+            word.map(u16::from_be_bytes)
+        })
+        .collect::<Result<Vec<u16>>>()?;
+
+    Ok(words)
+}
+
+fn read_as_u16_unchecked(bytes: &[u8]) -> Vec<u16> {
+    let err_msg = format!("failed to read byte array as Vec<u16> {:?}", bytes);
+    read_as_u16(bytes).expect(&err_msg)
+}
+
+#[derive(Debug)]
+struct EmptyUdpPacket(UdpPacket);
+
+impl EmptyUdpPacket {
+    fn set_data(mut self, data: &[u8]) -> UdpPacket {
+        self.0.data = data.to_vec();
+        self.0
+    }
+}
+
+// an IPv4 packet carrying anything, or a piece of one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ipv4Packet {
+    pub header: Ipv4Header,
+    pub payload: Vec<u8>,
+}
+
+impl Ipv4Packet {
+    // the whole packet, as it's sent
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header.to_bytes();
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+}
+
+#[derive(Debug)]
+pub struct UdpPacket {
+    ip_header: Ipv4Header,
+    udp_psuedo_header: UdpPseudoHeader,
+    udp_header: UdpHeader,
+    data: Vec<u8>,
+}
+
+impl UdpPacket {
+    // the UDP datagram an unfragmented IP packet carries
+    fn from_ipv4(packet: Ipv4Packet) -> Result<UdpPacket> {
+        let udp_bytes = packet
+            .payload
+            .get(..8)
+            .ok_or_else(|| anyhow!("there's no room for a UDP header in the IP packet"))?;
+        let (udp_psuedo_header, udp_header) = parse_udp_headers(&packet.header, udp_bytes)?;
+
+        Ok(UdpPacket {
+            udp_psuedo_header,
+            udp_header,
+            data: packet.payload[8..].to_vec(),
+            ip_header: packet.header,
+        })
+    }
+
+    // the IP header, options and all, then the UDP header. anything after them is ignored
+    fn parse_headers(bytes: &[u8]) -> Result<EmptyUdpPacket> {
+        let ip_header = Ipv4Header::from_bytes(bytes)?;
+        let udp_start = ip_header.header_length();
+        let udp_bytes = bytes
+            .get(udp_start..udp_start + 8)
+            .ok_or_else(|| anyhow!("there's no room for a UDP header after the IP header"))?;
+        let (udp_psuedo_header, udp_header) = parse_udp_headers(&ip_header, udp_bytes)?;
+
+        Ok(EmptyUdpPacket(UdpPacket {
+            ip_header,
+            udp_psuedo_header,
+            udp_header,
+            data: Vec::new(),
+        }))
+    }
+
+    pub fn ip_header(&self) -> &Ipv4Header {
+        &self.ip_header
+    }
+
+    fn valid_ip_checksum(&self) -> bool {
+        self.ip_header.valid_checksum()
+    }
+
+    fn valid_udp_checksum(&self) -> bool {
+        self.udp_sum() == 0xffff
+    }
+
+    // one's complement sum of the pseudo header, UDP header and data
+    fn udp_sum(&self) -> u16 {
+        let mut bytes: Vec<u8> = Vec::with_capacity(20);
+
+        // https://en.wikipedia.org/wiki/User_Datagram_Protocol#IPv4_pseudo_header
+
+        // Source Address
+        // Destination Address
+        // Zeroes
+        // Protocol
+        // UDP Length
+
+        // Source Port
+        // Destination Port
+        // Length
+        // Checksum
+
+        // Data
+
+        bytes.append(&mut self.udp_psuedo_header.source_address.octets().to_vec());
+        bytes.append(&mut self.udp_psuedo_header.destination_address.octets().to_vec());
+        bytes.push(0x00);
+        bytes.push(self.udp_psuedo_header.protocol);
+        bytes.append(&mut self.udp_psuedo_header.udp_length.to_be_bytes().to_vec());
+
+        bytes.append(&mut self.udp_header.source_port.to_be_bytes().to_vec());
+        bytes.append(&mut self.udp_header.destination_port.to_be_bytes().to_vec());
+        bytes.append(&mut self.udp_header.length.to_be_bytes().to_vec());
+        bytes.append(&mut self.udp_header.checksum.to_be_bytes().to_vec());
+
+        bytes.extend_from_slice(&self.data);
+
+        if !bytes.len().is_multiple_of(2) {
+            bytes.push(0)
+        }
+
+        read_as_u16_unchecked(&bytes)
+            .iter()
+            .fold(0xffff, |sum, &next| ones_complement_sum(sum, next))
+    }
+
+    fn valid_checksums(&self) -> bool {
+        let valid_udp = self.valid_udp_checksum();
+        let valid_ip = self.valid_ip_checksum();
+
+        valid_ip && valid_udp
+    }
+}
+
+#[test]
+fn test_udp_parse() -> Result<()> {
+    // ip cksum 0c741
+    //udp cksum xcc52
+
+    let bytes: [u8; 40] = [
+        // ip header
+        0x45, 0x00, // stuff i can ignore :)
+        0x00, 0x28, // total length (40)
+        0xb5, 0x81, 0x00, 0x00, 0x40, // stuff i can ignore :)
+        0x11, // protocol (17 -> UDP)
+        0xc7, 0x41, // ip cksum
+        0x7f, 0x00, 0x00, 0x01, // src addr (127.0.0.1)
+        0x7f, 0x00, 0x00, 0x01, // dest addr (127.0.0.1)
+        // no options
+
+        // udp header
+        0xc9, 0x64, // src port (51556)
+        0x1f, 0xbd, // dest port (8125)
+        0x00, 0x14, // udp length (header + data, 8 + 12 = 20)
+        0xcc, 0x52, // udp chksum
+        // data
+        0x72, 0x75, 0x73, 0x74, 0x20, 0x69, 0x73, 0x20, 0x63, 0x6f, 0x6f,
+        0x6c, // (rust is cool)
+    ];
+
+    let packet = UdpPacket::parse_headers(&bytes[..28])?.set_data(&bytes[28..]);
+
+    assert_eq!(packet.ip_header.source, Ipv4Addr::new(127, 0, 0, 1));
+    assert_eq!(packet.ip_header.destination, Ipv4Addr::new(127, 0, 0, 1));
+    assert!(packet.ip_header.valid_checksum());
+
+    assert_eq!(packet.udp_psuedo_header.source_address, Ipv4Addr::LOCALHOST);
+    assert_eq!(
+        packet.udp_psuedo_header.destination_address,
+        Ipv4Addr::LOCALHOST
+    );
+    assert_eq!(packet.udp_psuedo_header.protocol, 17);
+    assert_eq!(packet.udp_psuedo_header.udp_length, 20);
+
+    assert_eq!(packet.udp_header.source_port, 51556);
+    assert_eq!(packet.udp_header.destination_port, 8125);
+    assert_eq!(packet.udp_header.length, 20);
+
+    assert!(packet.valid_udp_checksum());
+    assert!(packet.valid_checksums()); // already checked individually, but make sure this method works
+
+    Ok(())
+}
+
+// https://en.wikipedia.org/wiki/Internet_Protocol_version_4#Header
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ipv4Header {
+    pub version: u8,
+    // in 32 bit words, 5 unless there are options
+    pub ihl: u8,
+    // differentiated services code point, the top 6 bits of the old type of service byte
+    pub dscp: u8,
+    // explicit congestion notification, the bottom 2
+    pub ecn: u8,
+    // header and data, in bytes
+    pub total_length: u16,
+    pub identification: u16,
+    // reserved, don't fragment, more fragments, from the top bit down
+    pub flags: u8,
+    // in 8 byte units
+    pub fragment_offset: u16,
+    pub time_to_live: u8,
+    pub protocol: u8,
+    pub checksum: u16,
+    pub source: Ipv4Addr,
+    pub destination: Ipv4Addr,
+    pub options: Vec<Ipv4Option>,
+}
+
+pub const PROTOCOL_UDP: u8 = 17;
+
+// the end of the option list is left out, anything after it is taken to be zero padding. no-ops
+// are kept, with no data, so the header goes back together byte for byte
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ipv4Option {
+    // copied, class and number all in one, like it's sent
+    pub kind: u8,
+    // without the type and length bytes
+    pub data: Vec<u8>,
+}
+
+impl Ipv4Option {
+    const END: u8 = 0;
+    const NO_OPERATION: u8 = 1;
+
+    // whether fragments get a copy of the option
+    pub fn copied(&self) -> bool {
+        self.kind & 0x80 != 0
+    }
+
+    pub fn class(&self) -> u8 {
+        (self.kind >> 5) & 0b11
+    }
+
+    pub fn number(&self) -> u8 {
+        self.kind & 0x1f
+    }
+}
+
+fn parse_options(mut bytes: &[u8]) -> Result<Vec<Ipv4Option>> {
+    let mut options = Vec::new();
+
+    while let Some((&kind, rest)) = bytes.split_first() {
+        match kind {
+            Ipv4Option::END => break,
+            Ipv4Option::NO_OPERATION => {
+                options.push(Ipv4Option {
+                    kind,
+                    data: Vec::new(),
+                });
+                bytes = rest;
+            }
+            _ => {
+                // the length counts the type and length bytes too
+                let length = *rest
+                    .first()
+                    .ok_or_else(|| anyhow!("option {} has no length", kind))?
+                    as usize;
+                ensure!(
+                    (2..=bytes.len()).contains(&length),
+                    "option {} has length {}, with {} bytes of options left",
+                    kind,
+                    length,
+                    bytes.len()
+                );
+                options.push(Ipv4Option {
+                    kind,
+                    data: bytes[2..length].to_vec(),
+                });
+                bytes = &bytes[length..];
+            }
+        }
+    }
+    Ok(options)
+}
+
+impl Ipv4Header {
+    // the header is at the start of bytes, anything after it is ignored
+    pub fn from_bytes(bytes: &[u8]) -> Result<Ipv4Header> {
+        ensure!(
+            bytes.len() >= 20,
+            "an IPv4 header is at least 20 bytes, not {}",
+            bytes.len()
+        );
+
+        let version = bytes[0] >> 4;
+        let ihl = bytes[0] & 0x0f;
+        ensure!(version == 4, "IP version {} isn't 4", version);
+        ensure!(ihl >= 5, "the header length {} is less than 5 words", ihl);
+
+        let length = ihl as usize * 4;
+        ensure!(
+            bytes.len() >= length,
+            "the header is {} bytes, there are only {}",
+            length,
+            bytes.len()
+        );
+        let bytes = &bytes[..length];
+
+        let total_length = u16::from_be_bytes([bytes[2], bytes[3]]);
+        ensure!(
+            total_length as usize >= length,
+            "the total length {} is less than the {} byte header",
+            total_length,
+            length
+        );
+
+        let flags_and_offset = u16::from_be_bytes([bytes[6], bytes[7]]);
+        let src: [u8; 4] = bytes[12..16].try_into()?;
+        let dst: [u8; 4] = bytes[16..20].try_into()?;
+
+        Ok(Ipv4Header {
+            version,
+            ihl,
+            dscp: bytes[1] >> 2,
+            ecn: bytes[1] & 0b11,
+            total_length,
+            identification: u16::from_be_bytes([bytes[4], bytes[5]]),
+            flags: (flags_and_offset >> 13) as u8,
+            fragment_offset: flags_and_offset & 0x1fff,
+            time_to_live: bytes[8],
+            protocol: bytes[9],
+            checksum: u16::from_be_bytes([bytes[10], bytes[11]]),
+            source: Ipv4Addr::from(src),
+            destination: Ipv4Addr::from(dst),
+            options: parse_options(&bytes[20..])?,
+        })
+    }
+
+    // in bytes, options included
+    pub fn header_length(&self) -> usize {
+        self.ihl as usize * 4
+    }
+
+    pub fn dont_fragment(&self) -> bool {
+        self.flags & 0b010 != 0
+    }
+
+    pub fn more_fragments(&self) -> bool {
+        self.flags & 0b001 != 0
+    }
+
+    pub fn is_fragment(&self) -> bool {
+        self.more_fragments() || self.fragment_offset > 0
+    }
+
+    pub fn valid_checksum(&self) -> bool {
+        self.sum() == 0xffff
+    }
+
+    // the header as it's sent, with the options padded out to the header length
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.header_length());
+        bytes.push(self.version << 4 | self.ihl);
+        bytes.push(self.dscp << 2 | self.ecn);
+        bytes.extend_from_slice(&self.total_length.to_be_bytes());
+        bytes.extend_from_slice(&self.identification.to_be_bytes());
+        let flags_and_offset = (self.flags as u16) << 13 | self.fragment_offset;
+        bytes.extend_from_slice(&flags_and_offset.to_be_bytes());
+        bytes.push(self.time_to_live);
+        bytes.push(self.protocol);
+        bytes.extend_from_slice(&self.checksum.to_be_bytes());
+        bytes.extend_from_slice(&self.source.octets());
+        bytes.extend_from_slice(&self.destination.octets());
+        for option in &self.options {
+            bytes.push(option.kind);
+            if option.kind != Ipv4Option::NO_OPERATION {
+                bytes.push(option.data.len() as u8 + 2);
+                bytes.extend_from_slice(&option.data);
+            }
+        }
+        bytes.resize(self.header_length(), Ipv4Option::END);
+        bytes
+    }
+
+    // the same header with the checksum worked out again, after changing some fields. it's read
+    // back in to make sure the fields still fit together
+    pub fn with_checksum(&self) -> Result<Ipv4Header> {
+        let header = Ipv4Header {
+            checksum: 0,
+            ..self.clone()
+        };
+        let checksum = !header.sum();
+        Ipv4Header::from_bytes(&Ipv4Header { checksum, ..header }.to_bytes())
+    }
+
+    // over the fields as they are now, so changing one shows up as a bad checksum
+    fn sum(&self) -> u16 {
+        read_as_u16_unchecked(&self.to_bytes())
+            .iter()
+            .fold(0xffff, |sum, &next| ones_complement_sum(sum, next))
+    }
+}
+
+#[test]
+fn test_from_bytes() -> Result<()> {
+    // a random packet from tcpdump
+    let packet = [
+        0x45, 0x00, 0x00, 0xd0, 0xb4, 0x2a, 0x40, 0x00, 0x40, 0x06, 0xc2, 0xd8, 0xac, 0x18, 0xba,
+        0xf2, 0xac, 0x18, 0xb0, 0x01,
+    ];
+    let out = Ipv4Header::from_bytes(&packet)?;
+    assert_eq!(out.source, Ipv4Addr::new(172, 24, 186, 242));
+    assert_eq!(out.destination, Ipv4Addr::new(172, 24, 176, 1));
+    assert_eq!(out.version, 4);
+    assert_eq!(out.header_length(), 20);
+    assert_eq!((out.dscp, out.ecn), (0, 0));
+    assert_eq!(out.total_length, 208);
+    assert_eq!(out.identification, 0xb42a);
+    assert!(out.dont_fragment() && !out.more_fragments());
+    assert_eq!(out.fragment_offset, 0);
+    assert_eq!(out.time_to_live, 64);
+    assert_eq!(out.protocol, 6);
+    assert_eq!(out.checksum, 0xc2d8);
+    assert!(out.options.is_empty());
+    assert!(out.valid_checksum());
+
+    Ok(())
+}
+
+#[test]
+fn test_options() -> Result<()> {
+    let mut bytes = vec![
+        0x47, 0xb9, 0x00, 0x20, 0x12, 0x34, 0x20, 0x03, 0x01, 0x11, 0x00, 0x00, 0x0a, 0x01, 0x01,
+        0x0a, 0x0a, 0x01, 0x01, 0xc8, // no-op, router alert, end of options, padding
+        0x01, 0x94, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+    let checksum = !Ipv4Header::from_bytes(&bytes)?.sum();
+    bytes[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+    let header = Ipv4Header::from_bytes(&bytes)?;
+    assert_eq!(header.header_length(), 28);
+    assert_eq!((header.dscp, header.ecn), (46, 1));
+    assert!(!header.dont_fragment() && header.more_fragments());
+    assert_eq!(header.fragment_offset, 3);
+    assert_eq!(
+        header.options,
+        [
+            Ipv4Option {
+                kind: 0x01,
+                data: Vec::new(),
+            },
+            Ipv4Option {
+                kind: 0x94,
+                data: vec![0x00, 0x00],
+            }
+        ]
+    );
+    assert!(header.options[1].copied());
+    assert_eq!(
+        (header.options[1].class(), header.options[1].number()),
+        (0, 20)
+    );
+    assert!(header.valid_checksum());
+    assert_eq!(header.to_bytes(), bytes);
+
+    // the checksum is over the fields, not the bytes they were read from
+    let mut changed = Ipv4Header {
+        time_to_live: 0,
+        ..header.clone()
+    };
+    assert!(!changed.valid_checksum());
+    assert!(changed.with_checksum()?.valid_checksum());
+    changed = header.clone();
+    changed.options.remove(0);
+    assert!(!changed.valid_checksum());
+
+    for (i, bad) in &[
+        // version 6
+        (0, 0x65),
+        // less than 5 words
+        (0, 0x44),
+        // more words than there are bytes
+        (0, 0x48),
+        // total length shorter than the header
+        (3, 0x1b),
+        // the option runs past the header
+        (22, 0x09),
+        // the option is too short to hold its own length
+        (22, 0x01),
+    ] {
+        let mut bytes = bytes.clone();
+        bytes[*i] = *bad;
+        assert!(Ipv4Header::from_bytes(&bytes).is_err(), "{} at {}", bad, i);
+    }
+    Ok(())
+}
+#[derive(Debug)]
+struct UdpPseudoHeader {
+    source_address: Ipv4Addr,
+    destination_address: Ipv4Addr,
+    protocol: u8,
+    udp_length: u16,
+}
+#[derive(Debug)]
+struct UdpHeader {
+    source_port: u16,
+    destination_port: u16,
+    length: u16, // wtf two lengths (https://stackoverflow.com/a/26356487)
+    checksum: u16,
+}
+
+fn parse_udp_headers(ip_header: &Ipv4Header, bytes: &[u8]) -> Result<(UdpPseudoHeader, UdpHeader)> {
+    ensure!(
+        ip_header.protocol == PROTOCOL_UDP,
+        "protocol {} isn't UDP",
+        ip_header.protocol
+    );
+    ensure!(
+        bytes.len() == 8,
+        anyhow!("Invalid header length={}", bytes.len())
+    );
+
+    let src: [u8; 2] = bytes[..2].try_into()?;
+    let dest: [u8; 2] = bytes[2..4].try_into()?;
+    let length: [u8; 2] = bytes[4..6].try_into()?;
+    let checksum: [u8; 2] = bytes[6..8].try_into()?;
+
+    // the length includes the 8 byte header
+    ensure!(
+        u16::from_be_bytes(length) >= 8,
+        anyhow!("Invalid UDP length={}", u16::from_be_bytes(length))
+    );
+
+    let psuedo_header = UdpPseudoHeader {
+        source_address: ip_header.source,
+        destination_address: ip_header.destination,
+        protocol: ip_header.protocol,
+        udp_length: u16::from_be_bytes(length),
+    };
+
+    let header = UdpHeader {
+        source_port: u16::from_be_bytes(src),
+        destination_port: u16::from_be_bytes(dest),
+        length: u16::from_be_bytes(length),
+        checksum: u16::from_be_bytes(checksum),
+    };
+
+    Ok((psuedo_header, header))
+}
+
+fn ones_complement_sum(x: u16, y: u16) -> u16 {
+    let sum: u32 = x as u32 + y as u32;
+
+    let low_word = (sum & 0xffff) as u16;
+    low_word + ((sum >> 16) as u16)
+}
+
+#[test]
+fn test_ones_complement_sum() {
+    //   0001 0110     22
+    // + 0000 0011      3
+    // ===========   ====
+    //   0001 1001     25z
+
+    let x = 0b_0000_0000_0001_0110;
+    let y = 0b_0000_0000_0000_0011;
+    assert_eq!(ones_complement_sum(x, y), 0b_0000_0000_0001_1001);
+
+    //   1111 1110     -1   (254)
+    // + 0000 0001      1
+    // ===========   ====
+    //   1111 1111     -0   (lol)
+
+    let x = 0b_0000_0000_1111_1110;
+    let y = 0b_0000_0000_0000_0001;
+    assert_eq!(ones_complement_sum(x, y), 0b_0000_0000_1111_1111);
+
+    //   1111 1110     -1   (254)
+    // + 0000 0011      3
+    // ===========   ====
+    // 1 0000 0001
+    // \________
+    //          \
+    // + 0000 0001
+    // ===========   ====
+    //   0000 0010      2
+
+    let x = 0b_1111_1111_1111_1110;
+    let y = 0b_0000_0000_0000_0011;
+    assert_eq!(ones_complement_sum(x, y), 0b_0000_0000_0000_0010);
+}
+
+const SOURCE: Ipv4Addr = Ipv4Addr::new(10, 1, 1, 10);
+const DESTINATION: Ipv4Addr = Ipv4Addr::new(10, 1, 1, 200);
+const DESTINATION_PORT: u16 = 42069;
+
+// a whole IPv4 packet with no options, carrying a UDP datagram with valid checksums
+fn udp_datagram(
+    source: SocketAddrV4,
+    destination: SocketAddrV4,
+    identification: u16,
+    data: &[u8],
+) -> Result<Vec<u8>> {
+    let total_length: u16 = (28 + data.len())
+        .try_into()
+        .map_err(|_| anyhow!("{} bytes is too much for one packet", data.len()))?;
+
+    let mut bytes = Vec::with_capacity(total_length as usize);
+    // version 4, 5 word header, no type of service
+    bytes.extend_from_slice(&[0x45, 0x00]);
+    bytes.extend_from_slice(&total_length.to_be_bytes());
+    bytes.extend_from_slice(&identification.to_be_bytes());
+    // don't fragment, time to live 64, UDP, checksum to fill in
+    bytes.extend_from_slice(&[0x40, 0x00, 0x40, 0x11, 0x00, 0x00]);
+    bytes.extend_from_slice(&source.ip().octets());
+    bytes.extend_from_slice(&destination.ip().octets());
+
+    bytes.extend_from_slice(&source.port().to_be_bytes());
+    bytes.extend_from_slice(&destination.port().to_be_bytes());
+    bytes.extend_from_slice(&(total_length - 20).to_be_bytes());
+    bytes.extend_from_slice(&[0x00, 0x00]);
+    bytes.extend_from_slice(data);
+
+    let ip_checksum = !Ipv4Header::from_bytes(&bytes[..20])?.sum();
+    bytes[10..12].copy_from_slice(&ip_checksum.to_be_bytes());
+
+    let packet = UdpPacket::parse_headers(&bytes)?.set_data(data);
+    // zero means there's no checksum, so a checksum of zero is sent as its other representation
+    let udp_checksum = match !packet.udp_sum() {
+        0 => 0xffff,
+        checksum => checksum,
+    };
+    bytes[26..28].copy_from_slice(&udp_checksum.to_be_bytes());
+
+    Ok(bytes)
+}
+
+// splits a whole packet into fragments of size bytes of payload each, and whatever's left for the
+// last one
+fn fragment(packet: &[u8], size: usize) -> Result<Vec<Vec<u8>>> {
+    ensure!(
+        size > 0 && size.is_multiple_of(8),
+        "fragments have to be a multiple of 8 bytes, not {}",
+        size
+    );
+    let header = Ipv4Header::from_bytes(packet)?;
+    let payload = &packet[header.header_length()..header.total_length as usize];
+
+    payload
+        .chunks(size)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let more = (i + 1) * size < payload.len();
+            let header = Ipv4Header {
+                total_length: (header.header_length() + chunk.len()) as u16,
+                // a fragment can't say don't fragment
+                flags: header.flags & 0b100 | more as u8,
+                fragment_offset: (i * size / 8) as u16,
+                ..header.clone()
+            }
+            .with_checksum()?;
+            Ok(Ipv4Packet {
+                header,
+                payload: chunk.to_vec(),
+            }
+            .to_bytes())
+        })
+        .collect()
+}
+
+#[test]
+fn test_fragments() -> Result<()> {
+    let source = SocketAddrV4::new(SOURCE, 1234);
+    let destination = SocketAddrV4::new(DESTINATION, DESTINATION_PORT);
+    let data = b"a datagram that's too big to go in one piece";
+    let fragments = fragment(&udp_datagram(source, destination, 7, data)?, 16)?;
+    assert_eq!(fragments.len(), 4);
+    assert!(fragments[..3].iter().all(|fragment| fragment.len() == 36));
+
+    // in any order, with a packet that isn't a fragment in the middle
+    let other = udp_datagram(source, destination, 8, b"meanwhile ")?;
+    let bytes = [
+        &fragments[2][..],
+        &fragments[0],
+        &other,
+        &fragments[3],
+        &fragments[1],
+    ]
+    .concat();
+    let (packets, errors) = parse_and_filter_packets(&bytes, &Filter::DEFAULT);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(packets.len(), 2);
+    assert_eq!(packets[0].data, b"meanwhile ");
+    assert_eq!(packets[1].data, data);
+    assert!(packets[1].valid_checksums());
+    assert!(!packets[1].ip_header().is_fragment());
+    assert_eq!(
+        packets[1].ip_header().total_length as usize,
+        28 + data.len()
+    );
+
+    assert!(fragment(&other, 12).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_udp_datagram() -> Result<()> {
+    let source = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 51556);
+    let destination = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 8125);
+    let bytes = udp_datagram(source, destination, 0xb581, b"rust is cool")?;
+
+    let packet = UdpPacket::parse_headers(&bytes)?.set_data(&bytes[28..]);
+    assert!(packet.valid_checksums());
+    assert_eq!(packet.udp_header.source_port, 51556);
+    assert_eq!(packet.udp_header.destination_port, 8125);
+    assert_eq!(packet.data, b"rust is cool");
+    Ok(())
+}
+
+// the packet with a router alert option squeezed in after the IP header
+#[cfg(test)]
+fn with_options(packet: &[u8]) -> Result<Vec<u8>> {
+    let mut bytes = packet[..20].to_vec();
+    bytes.extend_from_slice(&[0x94, 0x04, 0x00, 0x00]);
+    bytes.extend_from_slice(&packet[20..]);
+    bytes[0] = 0x46;
+    let total_length = u16::from_be_bytes([bytes[2], bytes[3]]) + 4;
+    bytes[2..4].copy_from_slice(&total_length.to_be_bytes());
+    bytes[10..12].copy_from_slice(&[0, 0]);
+    let checksum = !Ipv4Header::from_bytes(&bytes)?.sum();
+    bytes[10..12].copy_from_slice(&checksum.to_be_bytes());
+    Ok(bytes)
+}
+
+#[test]
+fn test_parse_options_and_protocol() -> Result<()> {
+    let source = SocketAddrV4::new(SOURCE, 1234);
+    let destination = SocketAddrV4::new(DESTINATION, DESTINATION_PORT);
+    let mut bytes = with_options(&udp_datagram(source, destination, 1, b"hello ")?)?;
+    bytes.extend_from_slice(&udp_datagram(source, destination, 2, b"world")?);
+
+    let (packets, errors) = parse_and_filter_packets(&bytes, &Filter::DEFAULT);
+    assert!(errors.is_empty());
+    assert_eq!(packets.len(), 2);
+    assert_eq!(packets[0].ip_header().header_length(), 24);
+    assert_eq!(packets[0].ip_header().options.len(), 1);
+    assert_eq!(packets[0].data, b"hello ");
+    assert_eq!(packets[1].data, b"world");
+
+    // the same packet claiming to be TCP
+    let mut tcp = udp_datagram(source, destination, 3, b"hello")?;
+    tcp[9] = 6;
+    let (packets, errors) = parse_and_filter_packets(&tcp, &Filter::DEFAULT);
+    assert!(packets.is_empty());
+    assert_eq!(errors[0].kind, FramingErrorKind::NotUdp { protocol: 6 });
+    Ok(())
+}
+
+// why some of the payload couldn't be read as a packet
+#[derive(Debug, Clone, PartialEq)]
+pub enum FramingErrorKind {
+    // the packet needs more bytes than there are left
+    Truncated {
+        needed: usize,
+        available: usize,
+    },
+    // the IP or UDP header doesn't make sense, e.g. the wrong version or a header length under
+    // 5 words
+    BadHeader(String),
+    NotUdp {
+        protocol: u8,
+    },
+    // the IP total length, less the IP header, and the UDP length should both be the length of
+    // the UDP datagram
+    LengthMismatch {
+        ip: usize,
+        udp: u16,
+    },
+    // every fragment but the last has to carry a multiple of 8 bytes
+    MisalignedFragment {
+        identification: u16,
+        length: usize,
+    },
+    // the fragments add up to more than the 65535 bytes an IP packet can have
+    FragmentTooLong {
+        identification: u16,
+        end: usize,
+    },
+    // fragments with different bytes for the same part of the packet, or past its end
+    OverlappingFragments {
+        identification: u16,
+    },
+    // the rest of the fragments didn't turn up in time. expected is the length of the payload,
+    // once the last fragment has said what it is
+    IncompletePacket {
+        identification: u16,
+        received: usize,
+        expected: Option<usize>,
+    },
+}
+
+impl fmt::Display for FramingErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FramingErrorKind::Truncated { needed, available } => write!(
+                f,
+                "the packet needs {} bytes, there are only {}",
+                needed, available
+            ),
+            FramingErrorKind::BadHeader(e) => write!(f, "bad header: {}", e),
+            FramingErrorKind::NotUdp { protocol } => write!(f, "protocol {} isn't UDP", protocol),
+            FramingErrorKind::LengthMismatch { ip, udp } => write!(
+                f,
+                "the IP header says the datagram is {} bytes, the UDP header says {}",
+                ip, udp
+            ),
+            FramingErrorKind::MisalignedFragment {
+                identification,
+                length,
+            } => write!(
+                f,
+                "a fragment of packet {:#06x} has {} bytes, not a multiple of 8",
+                identification, length
+            ),
+            FramingErrorKind::FragmentTooLong {
+                identification,
+                end,
+            } => write!(
+                f,
+                "the fragments of packet {:#06x} go on to byte {}, past the 65535 limit",
+                identification, end
+            ),
+            FramingErrorKind::OverlappingFragments { identification } => write!(
+                f,
+                "fragments of packet {:#06x} disagree about what's in it",
+                identification
+            ),
+            FramingErrorKind::IncompletePacket {
+                identification,
+                received,
+                expected: Some(expected),
+            } => write!(
+                f,
+                "only {} of the {} bytes of packet {:#06x} turned up",
+                received, expected, identification
+            ),
+            FramingErrorKind::IncompletePacket {
+                identification,
+                received,
+                expected: None,
+            } => write!(
+                f,
+                "only {} bytes of packet {:#06x} turned up, and not the end",
+                received, identification
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FramingError {
+    // where the bad packet starts in the payload, or in the capture file
+    pub offset: usize,
+    // up to the next thing that looks like a packet, or the end. for fragments it's all of them,
+    // wherever they were
+    pub skipped: usize,
+    pub kind: FramingErrorKind,
+}
+
+impl fmt::Display for FramingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "skipped {} bytes at offset {:#x}: {}",
+            self.skipped, self.offset, self.kind
+        )
+    }
+}
+
+// a packet and where it was in the payload
+#[derive(Debug, Clone, PartialEq)]
+pub struct FramedPacket {
+    pub offset: usize,
+    // how many bytes of the payload it took up, all the fragments' for a reassembled packet
+    pub length: usize,
+    pub packet: Ipv4Packet,
+}
+
+// the IPv4 packet at the start of bytes, as long as its header says it is
+fn frame(bytes: &[u8]) -> std::result::Result<Ipv4Packet, FramingErrorKind> {
+    let truncated = |needed| FramingErrorKind::Truncated {
+        needed,
+        available: bytes.len(),
+    };
+    if bytes.len() < 20 {
+        return Err(truncated(20));
+    }
+
+    let header =
+        Ipv4Header::from_bytes(bytes).map_err(|e| FramingErrorKind::BadHeader(e.to_string()))?;
+    let total_length = header.total_length as usize;
+    if bytes.len() < total_length {
+        return Err(truncated(total_length));
+    }
+
+    Ok(Ipv4Packet {
+        payload: bytes[header.header_length()..total_length].to_vec(),
+        header,
+    })
+}
+
+// after a bad packet, the next place that has a whole packet with a good IP checksum. a UDP
+// checksum could be bad on purpose, but one in 65536 random IP headers adds up. only the header is
+// read at each offset, the payload is copied once frame_packets takes the packet
+fn resync(bytes: &[u8], from: usize) -> usize {
+    (from..bytes.len())
+        .find(|&idx| {
+            let rest = &bytes[idx..];
+            matches!(
+                Ipv4Header::from_bytes(rest),
+                Ok(header) if header.total_length as usize <= rest.len() && header.valid_checksum()
+            )
+        })
+        .unwrap_or(bytes.len())
+}
+
+// every IPv4 packet in bytes, fragments and all, and what was skipped to find them
+pub fn frame_packets(bytes: &[u8]) -> (Vec<FramedPacket>, Vec<FramingError>) {
+    let mut idx = 0;
+    let mut packets = Vec::new();
+    let mut errors = Vec::new();
+
+    while idx < bytes.len() {
+        match frame(&bytes[idx..]) {
+            Ok(packet) => {
+                let length = packet.header.total_length as usize;
+                packets.push(FramedPacket {
+                    offset: idx,
+                    length,
+                    packet,
+                });
+                idx += length;
+            }
+            Err(kind) => {
+                let next = resync(bytes, idx + 1);
+                errors.push(FramingError {
+                    offset: idx,
+                    skipped: next - idx,
+                    kind,
+                });
+                idx = next;
+            }
+        }
+    }
+    (packets, errors)
+}
+
+// the UDP datagram in a whole IP packet. the IP header says how long it is, and the UDP header
+// has to agree
+fn udp(packet: Ipv4Packet) -> std::result::Result<UdpPacket, FramingErrorKind> {
+    if packet.header.protocol != PROTOCOL_UDP {
+        return Err(FramingErrorKind::NotUdp {
+            protocol: packet.header.protocol,
+        });
+    }
+    let ip = packet.payload.len();
+    let packet =
+        UdpPacket::from_ipv4(packet).map_err(|e| FramingErrorKind::BadHeader(e.to_string()))?;
+    let udp = packet.udp_header.length;
+    if ip != udp as usize {
+        return Err(FramingErrorKind::LengthMismatch { ip, udp });
+    }
+    Ok(packet)
+}
+
+// there are no timestamps in the payload, so how long to wait for the rest of a fragmented packet
+// is counted in packets
+const FRAGMENT_TIMEOUT: u64 = 64;
+
+// captures do have timestamps, in microseconds here, and linux waits 30 seconds
+const CAPTURE_FRAGMENT_TIMEOUT: u64 = 30_000_000;
+
+// the UDP datagrams in packets that arrived at the times given, in units of timeout, put back
+// together from fragments where they have to be
+fn datagrams(
+    framed: impl IntoIterator<Item = (FramedPacket, u64)>,
+    timeout: u64,
+    errors: &mut Vec<FramingError>,
+) -> Vec<UdpPacket> {
+    let mut reassembler = Reassembler::new(timeout);
+    let mut packets = Vec::new();
+
+    for (framed, now) in framed {
+        let (whole, mut fragment_errors) = reassembler.push(framed, now);
+        errors.append(&mut fragment_errors);
+        if let Some(whole) = whole {
+            match udp(whole.packet) {
+                Ok(packet) => packets.push(packet),
+                Err(kind) => errors.push(FramingError {
+                    offset: whole.offset,
+                    skipped: whole.length,
+                    kind,
+                }),
+            }
+        }
+    }
+
+    errors.extend(reassembler.finish());
+    errors.sort_by_key(|error| error.offset);
+    packets
+}
+
+// every UDP datagram in bytes, put back together from fragments where it has to be, good
+// checksums or not. and what was skipped to find them, in order
+pub fn parse_packets(bytes: &[u8]) -> (Vec<UdpPacket>, Vec<FramingError>) {
+    let (framed, mut errors) = frame_packets(bytes);
+    let now = 0..;
+    let packets = datagrams(framed.into_iter().zip(now), FRAGMENT_TIMEOUT, &mut errors);
+    (packets, errors)
+}
+
+// the same for the packets out of a capture file. each one is already framed by the capture, so
+// it's skipped on its own if it's bad, and anything after the end of it, like ethernet padding,
+// is ignored
+pub fn parse_captured_packets(captured: &[CapturedPacket]) -> (Vec<UdpPacket>, Vec<FramingError>) {
+    let mut errors = Vec::new();
+    let framed: Vec<(FramedPacket, u64)> = captured
+        .iter()
+        .filter_map(|captured| match frame(&captured.bytes) {
+            Ok(packet) => {
+                let framed = FramedPacket {
+                    offset: captured.offset,
+                    length: captured.bytes.len(),
+                    packet,
+                };
+                Some((framed, captured.timestamp.as_micros() as u64))
+            }
+            Err(kind) => {
+                errors.push(FramingError {
+                    offset: captured.offset,
+                    skipped: captured.bytes.len(),
+                    kind,
+                });
+                None
+            }
+        })
+        .collect();
+    let packets = datagrams(framed, CAPTURE_FRAGMENT_TIMEOUT, &mut errors);
+    (packets, errors)
+}
+
+fn filter_packets(packets: Vec<UdpPacket>, filter: &Filter) -> Vec<UdpPacket> {
+    packets
+        .into_iter()
+        .filter(|packet| filter.matches(packet))
+        .collect()
+}
+
+pub fn parse_and_filter_packets(
+    bytes: &[u8],
+    filter: &Filter,
+) -> (Vec<UdpPacket>, Vec<FramingError>) {
+    let (packets, errors) = parse_packets(bytes);
+    (filter_packets(packets, filter), errors)
+}
+
+pub fn parse_and_filter_captured_packets(
+    captured: &[CapturedPacket],
+    filter: &Filter,
+) -> (Vec<UdpPacket>, Vec<FramingError>) {
+    let (packets, errors) = parse_captured_packets(captured);
+    (filter_packets(packets, filter), errors)
+}
+
+#[test]
+fn test_framing_errors() -> Result<()> {
+    let source = SocketAddrV4::new(SOURCE, 1234);
+    let destination = SocketAddrV4::new(DESTINATION, DESTINATION_PORT);
+    let first = udp_datagram(source, destination, 1, b"one ")?;
+    let mut second = udp_datagram(source, destination, 2, b"two ")?;
+    let third = udp_datagram(source, destination, 3, b"three")?;
+    let data = |packets: &[UdpPacket]| -> Vec<u8> {
+        packets
+            .iter()
+            .flat_map(|packet| packet.data.clone())
+            .collect()
+    };
+
+    // a UDP length that's too long used to throw off every packet after it
+    second[25] += 10;
+    let bytes = [&first[..], &second, &third].concat();
+    let (packets, errors) = parse_and_filter_packets(&bytes, &Filter::DEFAULT);
+    assert_eq!(data(&packets), b"one three");
+    assert_eq!(
+        errors,
+        [FramingError {
+            offset: first.len(),
+            skipped: second.len(),
+            kind: FramingErrorKind::LengthMismatch { ip: 12, udp: 22 },
+        }]
+    );
+
+    // junk between packets, and a packet cut short at the end
+    let bytes = [&first[..], b"junk", &third, &first[..30]].concat();
+    let (packets, errors) = parse_and_filter_packets(&bytes, &Filter::DEFAULT);
+    assert_eq!(data(&packets), b"one three");
+    assert_eq!(errors.len(), 2);
+    assert_eq!((errors[0].offset, errors[0].skipped), (first.len(), 4));
+    assert!(matches!(errors[0].kind, FramingErrorKind::BadHeader(_)));
+    assert_eq!(
+        errors[1],
+        FramingError {
+            offset: bytes.len() - 30,
+            skipped: 30,
+            kind: FramingErrorKind::Truncated {
+                needed: 32,
+                available: 30
+            },
+        }
+    );
+    assert_eq!(
+        errors[1].to_string(),
+        "skipped 30 bytes at offset 0x45: the packet needs 32 bytes, there are only 30"
+    );
+
+    // every 4 bytes a header for a 64 KiB packet, none with a good checksum
+    let bytes = [&[0][..], &[0x45, 0x00, 0xff, 0xff].repeat(0x8000)].concat();
+    let (packets, errors) = frame_packets(&bytes);
+    assert!(packets.is_empty());
+    assert_eq!((errors.len(), errors[0].skipped), (1, bytes.len()));
+    Ok(())
+}
+
+pub struct Layer4 {
+    // which packets the document is made of
+    filter: Filter,
+}
+
+impl Layer4 {
+    pub const DEFAULT: Layer4 = Layer4::new(Filter::DEFAULT);
+
+    pub const fn new(filter: Filter) -> Self {
+        Layer4 { filter }
+    }
+
+    pub fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    // the document, and the parts of the payload that couldn't be read as packets
+    pub fn peel_with_errors(&self, bytes: &[u8]) -> Result<(Vec<u8>, Vec<FramingError>)> {
+        let (packets, errors) = parse_and_filter_packets(&decode(bytes)?, &self.filter);
+        let document = packets.into_iter().flat_map(|packet| packet.data).collect();
+        Ok((document, errors))
+    }
+
+    // every IPv4 packet in the payload as it was sent, fragments and noise included, for writing
+    // to a capture file. there are no timestamps, so they're all at 0
+    pub fn capture(&self, bytes: &[u8]) -> Result<(Vec<CapturedPacket>, Vec<FramingError>)> {
+        let bytes = decode(bytes)?;
+        let (framed, errors) = frame_packets(&bytes);
+        let captured = framed
+            .into_iter()
+            .map(|framed| CapturedPacket {
+                timestamp: Duration::ZERO,
+                offset: framed.offset,
+                bytes: bytes[framed.offset..framed.offset + framed.length].to_vec(),
+            })
+            .collect();
+        Ok((captured, errors))
+    }
+
+    // the document, out of a pcap or pcapng capture of the traffic rather than the payload
+    pub fn peel_capture(&self, capture: &[u8]) -> Result<(Vec<u8>, Vec<FramingError>)> {
+        let captured = pcap::read_capture(capture)?;
+        let (packets, errors) = parse_and_filter_captured_packets(&captured, &self.filter);
+        let document = packets.into_iter().flat_map(|packet| packet.data).collect();
+        Ok((document, errors))
+    }
+}
+
+impl Layer for Layer4 {
+    fn name(&self) -> &'static str {
+        "network"
+    }
+
+    fn title(&self) -> &'static str {
+        "Network Traffic"
+    }
+
+    fn description(&self) -> &'static str {
+        "Collect the UDP payloads sent from 10.1.1.10 to 10.1.1.200:42069 with valid checksums"
+    }
+
+    fn peel(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        self.peel_with_errors(bytes).map(|(document, _)| document)
+    }
+
+    // the parts of the payload that couldn't be read as packets
+    fn peel_with_diagnostics(&self, bytes: &[u8]) -> Result<(Vec<u8>, Vec<String>)> {
+        let (document, errors) = self.peel_with_errors(bytes)?;
+        Ok((
+            document,
+            errors.iter().map(FramingError::to_string).collect(),
+        ))
+    }
+
+    fn wrap(&self, document: &[u8]) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let mut rest = document;
+
+        while !rest.is_empty() {
+            let random = random_bytes(10)?;
+            let source_port = u16::from_be_bytes([random[0], random[1]]);
+            let identification = u16::from_be_bytes([random[2], random[3]]);
+            let len = rest.len().min(random[4] as usize + 1);
+            let (data, next) = rest.split_at(len);
+
+            let source = SocketAddrV4::new(SOURCE, source_port);
+            let destination = SocketAddrV4::new(DESTINATION, DESTINATION_PORT);
+
+            // about a quarter of the time, send a copy that has to be filtered out first
+            if random[5] < 64 {
+                let wrong_source = SocketAddrV4::new(Ipv4Addr::new(10, 1, 1, 11), source_port);
+                let wrong_port = SocketAddrV4::new(DESTINATION, DESTINATION_PORT + 1);
+                let noise = match random[6] % 3 {
+                    0 => udp_datagram(wrong_source, destination, identification, data)?,
+                    1 => udp_datagram(source, wrong_port, identification, data)?,
+                    _ => {
+                        // corrupt the data so the UDP checksum doesn't match
+                        let mut noise = udp_datagram(source, destination, identification, data)?;
+                        noise[28 + random[7] as usize % data.len()] ^= 0xff;
+                        noise
+                    }
+                };
+                bytes.extend_from_slice(&noise);
+            }
+
+            let datagram = udp_datagram(source, destination, identification, data)?;
+            // and about a quarter of the time, send it in pieces, which could be backwards
+            if random[8] < 64 {
+                let mut fragments = fragment(&datagram, 8 * (random[9] as usize % 8 + 1))?;
+                if random[9] & 0x80 != 0 {
+                    fragments.reverse();
+                }
+                fragments
+                    .iter()
+                    .for_each(|fragment| bytes.extend_from_slice(fragment));
+            } else {
+                bytes.extend_from_slice(&datagram);
+            }
+            rest = next;
+        }
+
+        Ok(encode(&bytes))
+    }
+
+    fn with_options(&self, options: &LayerOptions) -> Option<Box<dyn Layer>> {
+        let filter = options.filter.clone()?;
+        Some(Box::new(Layer4::new(filter)))
+    }
+}
+
+#[test]
+fn test_capture_round_trip() -> Result<()> {
+    let document = b"a document that gets sent in a few hundred bytes of packets. ".repeat(8);
+    let payload = Layer4::DEFAULT.wrap(&document)?;
+    let (captured, errors) = Layer4::DEFAULT.capture(&payload)?;
+    assert!(errors.is_empty());
+
+    for capture in &[pcap::write_pcap(&captured), pcap::write_pcapng(&captured)] {
+        let (peeled, errors) = Layer4::DEFAULT.peel_capture(capture)?;
+        assert_eq!(peeled, document);
+        assert!(errors.is_empty());
+    }
+
+    // fragments that arrive too far apart aren't put back together
+    let source = SocketAddrV4::new(SOURCE, 1234);
+    let destination = SocketAddrV4::new(DESTINATION, DESTINATION_PORT);
+    let datagram = udp_datagram(source, destination, 7, b"sixteen bytes...")?;
+    let captured: Vec<CapturedPacket> = fragment(&datagram, 8)?
+        .into_iter()
+        .enumerate()
+        .map(|(i, bytes)| CapturedPacket {
+            timestamp: Duration::from_secs(31 * i as u64),
+            offset: 0,
+            bytes,
+        })
+        .collect();
+    let (packets, errors) = parse_and_filter_captured_packets(&captured, &Filter::DEFAULT);
+    assert!(packets.is_empty());
+    assert_eq!(errors.len(), 3);
+    Ok(())
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_parse_arbitrary(bytes in proptest::collection::vec(0..=255u8, 0..256)) {
+        // every byte is either in a packet or skipped
+        let (packets, errors) = frame_packets(&bytes);
+        let framed: usize = packets.iter().map(|packet| packet.length).sum();
+        let skipped: usize = errors.iter().map(|error| error.skipped).sum();
+        proptest::prop_assert_eq!(framed + skipped, bytes.len());
+    }
+}
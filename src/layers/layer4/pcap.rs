@@ -0,0 +1,497 @@
+// reading and writing capture files, pcap and pcapng, so packets can go to and from wireshark.
+// https://www.tcpdump.org/manpages/pcap-savefile.5.txt and
+// https://www.ietf.org/archive/id/draft-ietf-opsawg-pcapng-01.html
+use anyhow::{anyhow, bail, ensure, Result};
+use std::time::Duration;
+
+pub const LINKTYPE_ETHERNET: u32 = 1;
+// the packet starts with an IPv4 or IPv6 header
+pub const LINKTYPE_RAW: u32 = 101;
+// the packet starts with an IPv4 header
+pub const LINKTYPE_IPV4: u32 = 228;
+
+const PCAP_MICROSECONDS: u32 = 0xa1b2_c3d4;
+const PCAP_NANOSECONDS: u32 = 0xa1b2_3c4d;
+
+const SECTION_HEADER_BLOCK: u32 = 0x0a0d_0d0a;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 1;
+const SIMPLE_PACKET_BLOCK: u32 = 3;
+const ENHANCED_PACKET_BLOCK: u32 = 6;
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const IF_TSRESOL: u16 = 9;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+
+// an IPv4 packet out of a capture, without whatever the link layer put around it
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedPacket {
+    // since the epoch, or since whenever the capture started counting
+    pub timestamp: Duration,
+    // where the IPv4 packet starts in the capture file
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+}
+
+// reads numbers in whichever byte order the capture was written in
+struct Reader<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn get(&self, at: usize, len: usize) -> Result<&[u8]> {
+        self.bytes.get(at..at + len).ok_or_else(|| {
+            anyhow!(
+                "the capture ends at {} bytes, in the middle of something at offset {}",
+                self.bytes.len(),
+                at
+            )
+        })
+    }
+
+    fn u16(&self, at: usize) -> Result<u16> {
+        let bytes = [self.get(at, 2)?[0], self.get(at, 2)?[1]];
+        Ok(match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn u32(&self, at: usize) -> Result<u32> {
+        let bytes = self.get(at, 4)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Ok(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+}
+
+// where the IPv4 packet in a frame starts, None if there isn't one
+fn ip_start(linktype: u32, frame: &[u8]) -> Option<usize> {
+    let start = match linktype {
+        LINKTYPE_ETHERNET => {
+            // after the destination and source addresses, skipping any VLAN tags
+            let mut at = 12;
+            loop {
+                let ethertype = u16::from_be_bytes([*frame.get(at)?, *frame.get(at + 1)?]);
+                match ethertype {
+                    ETHERTYPE_VLAN | ETHERTYPE_QINQ => at += 4,
+                    ETHERTYPE_IPV4 => break at + 2,
+                    _ => return None,
+                }
+            }
+        }
+        _ => 0,
+    };
+    match frame.get(start)? >> 4 {
+        4 => Some(start),
+        _ => None,
+    }
+}
+
+fn check_linktype(linktype: u32) -> Result<()> {
+    match linktype {
+        LINKTYPE_ETHERNET | LINKTYPE_RAW | LINKTYPE_IPV4 => Ok(()),
+        _ => bail!(
+            "link type {} isn't supported, only Ethernet ({}) and raw IP ({} and {})",
+            linktype,
+            LINKTYPE_ETHERNET,
+            LINKTYPE_RAW,
+            LINKTYPE_IPV4
+        ),
+    }
+}
+
+// the frame at offset in bytes, as a packet if there's IPv4 in it
+fn captured(
+    bytes: &[u8],
+    offset: usize,
+    len: usize,
+    linktype: u32,
+    timestamp: Duration,
+) -> Option<CapturedPacket> {
+    let frame = &bytes[offset..offset + len];
+    let start = ip_start(linktype, frame)?;
+    Some(CapturedPacket {
+        timestamp,
+        offset: offset + start,
+        bytes: frame[start..].to_vec(),
+    })
+}
+
+// the IPv4 packets in a pcap or pcapng file, in the order they were captured. anything else,
+// like ARP or IPv6, is left out
+pub fn read_capture(bytes: &[u8]) -> Result<Vec<CapturedPacket>> {
+    let magic = |big_endian| Reader { bytes, big_endian }.u32(0);
+    match (magic(false)?, magic(true)?) {
+        (SECTION_HEADER_BLOCK, _) => read_pcapng(bytes),
+        (PCAP_MICROSECONDS | PCAP_NANOSECONDS, _) => read_pcap(bytes, false),
+        (_, PCAP_MICROSECONDS | PCAP_NANOSECONDS) => read_pcap(bytes, true),
+        (magic, _) => bail!("{:#010x} isn't the start of a pcap or pcapng file", magic),
+    }
+}
+
+fn read_pcap(bytes: &[u8], big_endian: bool) -> Result<Vec<CapturedPacket>> {
+    let reader = Reader { bytes, big_endian };
+    let nanoseconds = reader.u32(0)? == PCAP_NANOSECONDS;
+    let linktype = reader.u32(20)? & 0x0fff_ffff;
+    check_linktype(linktype)?;
+
+    let mut packets = Vec::new();
+    let mut at = 24;
+    while at < bytes.len() {
+        let seconds = reader.u32(at)? as u64;
+        let fraction = reader.u32(at + 4)?;
+        let len = reader.u32(at + 8)? as usize;
+        reader.get(at + 16, len)?;
+
+        let timestamp = match nanoseconds {
+            true => Duration::new(seconds, fraction),
+            false => Duration::new(seconds, 0) + Duration::from_micros(fraction as u64),
+        };
+        packets.extend(captured(bytes, at + 16, len, linktype, timestamp));
+        at += 16 + len;
+    }
+    Ok(packets)
+}
+
+// an interface packets are captured on, from its description block
+struct Interface {
+    linktype: u32,
+    // if_tsresol, the top bit says whether the rest is a power of 2 rather than 10
+    resolution: u8,
+}
+
+impl Interface {
+    fn timestamp(&self, units: u64) -> Duration {
+        let per_second: u128 = match self.resolution {
+            r if r & 0x80 != 0 => 1 << (r & 0x7f).min(64),
+            r => 10u128.saturating_pow(r as u32),
+        };
+        let units = units as u128;
+        let seconds = (units / per_second).min(u64::MAX as u128) as u64;
+        let nanos = (units % per_second * 1_000_000_000 / per_second) as u32;
+        Duration::new(seconds, nanos)
+    }
+}
+
+fn read_pcapng(bytes: &[u8]) -> Result<Vec<CapturedPacket>> {
+    let mut reader = Reader {
+        bytes,
+        big_endian: false,
+    };
+    let mut interfaces: Vec<Interface> = Vec::new();
+    let mut packets = Vec::new();
+
+    let mut at = 0;
+    while at < bytes.len() {
+        let block_type = reader.u32(at)?;
+        if block_type == SECTION_HEADER_BLOCK {
+            // each section can be in a different byte order, and has its own interfaces
+            reader.big_endian = match reader.get(at + 8, 4)? {
+                [0x1a, 0x2b, 0x3c, 0x4d] => true,
+                [0x4d, 0x3c, 0x2b, 0x1a] => false,
+                _ => bail!("bad byte order magic in the section at offset {}", at),
+            };
+            interfaces.clear();
+        }
+
+        let len = reader.u32(at + 4)? as usize;
+        ensure!(
+            len >= 12 && len.is_multiple_of(4),
+            "bad block length {} at offset {}",
+            len,
+            at
+        );
+        ensure!(
+            reader.u32(at + len - 4)? as usize == len,
+            "the lengths at either end of the block at offset {} don't match",
+            at
+        );
+        let body = at + 8;
+        let body_len = len - 12;
+
+        match block_type {
+            SECTION_HEADER_BLOCK => ensure!(
+                reader.u32(body)? == BYTE_ORDER_MAGIC,
+                "bad section header at offset {}",
+                at
+            ),
+            INTERFACE_DESCRIPTION_BLOCK => {
+                let linktype = reader.u16(body)? as u32;
+                check_linktype(linktype)?;
+                interfaces.push(Interface {
+                    linktype,
+                    resolution: read_resolution(&reader, body + 8, body + body_len)?,
+                });
+            }
+            ENHANCED_PACKET_BLOCK => {
+                let interface = reader.u32(body)? as usize;
+                let interface = interfaces.get(interface).ok_or_else(|| {
+                    anyhow!("the packet at offset {} is from an unknown interface", at)
+                })?;
+                let units = (reader.u32(body + 4)? as u64) << 32 | reader.u32(body + 8)? as u64;
+                let captured_len = reader.u32(body + 12)? as usize;
+                ensure!(
+                    20 + captured_len <= body_len,
+                    "the packet at offset {} is longer than its block",
+                    at
+                );
+                let timestamp = interface.timestamp(units);
+                packets.extend(captured(
+                    bytes,
+                    body + 20,
+                    captured_len,
+                    interface.linktype,
+                    timestamp,
+                ));
+            }
+            SIMPLE_PACKET_BLOCK => {
+                // always from the first interface, with no timestamp. the block is padded, so
+                // the original length says how much of it is the packet
+                let interface = interfaces.first().ok_or_else(|| {
+                    anyhow!("the packet at offset {} is from an unknown interface", at)
+                })?;
+                ensure!(
+                    body_len >= 4,
+                    "the simple packet block at offset {} is too short for a packet",
+                    at
+                );
+                let original_len = reader.u32(body)? as usize;
+                let captured_len = original_len.min(body_len - 4);
+                packets.extend(captured(
+                    bytes,
+                    body + 4,
+                    captured_len,
+                    interface.linktype,
+                    Duration::ZERO,
+                ));
+            }
+            // statistics, name resolution and so on
+            _ => {}
+        }
+        at += len;
+    }
+    Ok(packets)
+}
+
+// if_tsresol out of an interface's options, microseconds if it isn't there
+fn read_resolution(reader: &Reader, mut at: usize, end: usize) -> Result<u8> {
+    while at + 4 <= end {
+        let code = reader.u16(at)?;
+        let len = reader.u16(at + 2)? as usize;
+        match code {
+            // the end of the options
+            0 => break,
+            IF_TSRESOL if len == 1 => return Ok(reader.get(at + 4, 1)?[0]),
+            _ => at += 4 + len.div_ceil(4) * 4,
+        }
+    }
+    Ok(6)
+}
+
+// a pcap file of raw IP packets, with microsecond timestamps
+pub fn write_pcap(packets: &[CapturedPacket]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&PCAP_MICROSECONDS.to_le_bytes());
+    // version 2.4
+    out.extend_from_slice(&2u16.to_le_bytes());
+    out.extend_from_slice(&4u16.to_le_bytes());
+    // no time zone, no accuracy, and no snapshot length beyond the biggest IP packet
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&0xffffu32.to_le_bytes());
+    out.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+
+    for packet in packets {
+        let len = packet.bytes.len() as u32;
+        out.extend_from_slice(&(packet.timestamp.as_secs() as u32).to_le_bytes());
+        out.extend_from_slice(&packet.timestamp.subsec_micros().to_le_bytes());
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&packet.bytes);
+    }
+    out
+}
+
+fn write_block(out: &mut Vec<u8>, block_type: u32, body: &[u8]) {
+    let padding = body.len().next_multiple_of(4) - body.len();
+    let len = (12 + body.len() + padding) as u32;
+    out.extend_from_slice(&block_type.to_le_bytes());
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(body);
+    out.extend(std::iter::repeat_n(0, padding));
+    out.extend_from_slice(&len.to_le_bytes());
+}
+
+// the same as a pcapng file, with one section and one raw IP interface
+pub fn write_pcapng(packets: &[CapturedPacket]) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    let mut section = Vec::new();
+    section.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+    // version 1.0, and the length of the section isn't given
+    section.extend_from_slice(&1u16.to_le_bytes());
+    section.extend_from_slice(&0u16.to_le_bytes());
+    section.extend_from_slice(&(-1i64).to_le_bytes());
+    write_block(&mut out, SECTION_HEADER_BLOCK, &section);
+
+    let mut interface = Vec::new();
+    interface.extend_from_slice(&(LINKTYPE_RAW as u16).to_le_bytes());
+    interface.extend_from_slice(&0u16.to_le_bytes());
+    interface.extend_from_slice(&0xffffu32.to_le_bytes());
+    write_block(&mut out, INTERFACE_DESCRIPTION_BLOCK, &interface);
+
+    for packet in packets {
+        let micros = packet.timestamp.as_micros() as u64;
+        let len = packet.bytes.len() as u32;
+        let mut block = Vec::with_capacity(20 + packet.bytes.len());
+        block.extend_from_slice(&0u32.to_le_bytes());
+        block.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+        block.extend_from_slice(&(micros as u32).to_le_bytes());
+        block.extend_from_slice(&len.to_le_bytes());
+        block.extend_from_slice(&len.to_le_bytes());
+        block.extend_from_slice(&packet.bytes);
+        write_block(&mut out, ENHANCED_PACKET_BLOCK, &block);
+    }
+    out
+}
+
+#[cfg(test)]
+fn packets() -> Vec<CapturedPacket> {
+    vec![
+        CapturedPacket {
+            timestamp: Duration::new(1_600_000_000, 250_000_000),
+            offset: 0,
+            bytes: vec![0x45, 1, 2, 3, 4],
+        },
+        CapturedPacket {
+            timestamp: Duration::new(1_600_000_001, 1_000),
+            offset: 0,
+            bytes: vec![0x46; 23],
+        },
+    ]
+}
+
+#[test]
+fn test_round_trip() -> Result<()> {
+    let packets = packets();
+    for (file, header) in &[(write_pcap(&packets), 24), (write_pcapng(&packets), 48)] {
+        let read = read_capture(file)?;
+        assert_eq!(read.len(), 2);
+        for (read, packet) in read.iter().zip(&packets) {
+            assert_eq!(read.timestamp, packet.timestamp);
+            assert_eq!(read.bytes, packet.bytes);
+            assert_eq!(
+                &file[read.offset..read.offset + read.bytes.len()],
+                &read.bytes[..]
+            );
+        }
+        assert!(read[0].offset >= *header);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_read_ethernet() -> Result<()> {
+    // big endian, nanosecond timestamps, Ethernet
+    let mut file = Vec::new();
+    file.extend_from_slice(&PCAP_NANOSECONDS.to_be_bytes());
+    file.extend_from_slice(&[
+        0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 0, 1,
+    ]);
+    let mut record = |seconds: u32, nanos: u32, frame: &[u8]| {
+        file.extend_from_slice(&seconds.to_be_bytes());
+        file.extend_from_slice(&nanos.to_be_bytes());
+        file.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        file.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        file.extend_from_slice(frame);
+    };
+    let addresses = [0xaa; 12];
+    record(5, 7, &[&addresses[..], &[0x08, 0x00, 0x45, 9]].concat());
+    // ARP
+    record(6, 0, &[&addresses[..], &[0x08, 0x06, 0, 1]].concat());
+    // tagged with a VLAN
+    record(
+        7,
+        0,
+        &[&addresses[..], &[0x81, 0x00, 0, 5, 0x08, 0x00, 0x45, 8]].concat(),
+    );
+
+    let read = read_capture(&file)?;
+    assert_eq!(read.len(), 2);
+    assert_eq!(read[0].timestamp, Duration::new(5, 7));
+    assert_eq!(read[0].bytes, [0x45, 9]);
+    assert_eq!(read[0].offset, 24 + 16 + 14);
+    assert_eq!(read[1].bytes, [0x45, 8]);
+    Ok(())
+}
+
+#[test]
+fn test_read_pcapng() -> Result<()> {
+    let mut file = write_pcapng(&packets()[..1]);
+    // an interface with nanosecond timestamps, after a block that's skipped
+    write_block(&mut file, 5, &[1, 2, 3, 4]);
+    let mut interface = vec![1, 0, 0, 0, 0, 0, 0, 0];
+    interface.extend_from_slice(&[9, 0, 1, 0, 9, 0, 0, 0, 0, 0, 0, 0]);
+    write_block(&mut file, INTERFACE_DESCRIPTION_BLOCK, &interface);
+    let ethernet = [&[0xbb; 12][..], &[0x08, 0x00, 0x45, 7, 7]].concat();
+    let mut block = vec![1, 0, 0, 0];
+    block.extend_from_slice(&0u32.to_le_bytes());
+    block.extend_from_slice(&1_000_000_123u32.to_le_bytes());
+    block.extend_from_slice(&(ethernet.len() as u32).to_le_bytes());
+    block.extend_from_slice(&(ethernet.len() as u32).to_le_bytes());
+    block.extend_from_slice(&ethernet);
+    write_block(&mut file, ENHANCED_PACKET_BLOCK, &block);
+    // a simple packet block is from the first interface
+    let mut simple = 3u32.to_le_bytes().to_vec();
+    simple.extend_from_slice(&[0x45, 6, 6]);
+    write_block(&mut file, SIMPLE_PACKET_BLOCK, &simple);
+
+    let read = read_capture(&file)?;
+    assert_eq!(read.len(), 3);
+    assert_eq!(read[1].timestamp, Duration::new(1, 123));
+    assert_eq!(read[1].bytes, [0x45, 7, 7]);
+    assert_eq!(read[2].bytes, [0x45, 6, 6]);
+    assert_eq!(read[2].timestamp, Duration::ZERO);
+    Ok(())
+}
+
+#[test]
+fn test_read_errors() {
+    let file = write_pcap(&packets());
+    let pcapng = write_pcapng(&packets());
+    let mut wrong_linktype = file.clone();
+    wrong_linktype[20] = 113;
+    let mut wrong_length = pcapng.clone();
+    wrong_length[4] = 32;
+    // a simple packet block with no room for its original length
+    let mut empty_simple = write_pcapng(&[]);
+    empty_simple.extend_from_slice(&[3, 0, 0, 0, 12, 0, 0, 0, 12, 0, 0, 0]);
+
+    for bad in &[
+        &b"not a capture"[..],
+        &file[..10],
+        &file[..file.len() - 1],
+        &wrong_linktype,
+        &pcapng[..pcapng.len() - 2],
+        &wrong_length,
+        &empty_simple,
+    ] {
+        assert!(read_capture(bad).is_err(), "{:?}", bad);
+    }
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_read_arbitrary(tail in proptest::collection::vec(0..=255u8, 0..256)) {
+        // whatever follows the headers, it's an error rather than a panic
+        for header in &[write_pcap(&[]), write_pcapng(&[])] {
+            let _ = read_capture(&[&header[..], &tail].concat());
+        }
+    }
+}
@@ -0,0 +1,448 @@
+// putting fragmented IPv4 packets back together
+use super::{FramedPacket, FramingError, FramingErrorKind, Ipv4Header, Ipv4Packet};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::net::Ipv4Addr;
+
+// fragments with the same key are pieces of the same packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FragmentKey {
+    pub source: Ipv4Addr,
+    pub destination: Ipv4Addr,
+    pub identification: u16,
+    pub protocol: u8,
+}
+
+impl FragmentKey {
+    pub fn of(header: &Ipv4Header) -> Self {
+        FragmentKey {
+            source: header.source,
+            destination: header.destination,
+            identification: header.identification,
+            protocol: header.protocol,
+        }
+    }
+}
+
+// the biggest an IPv4 packet can be, header included
+const MAX_LENGTH: usize = 0xffff;
+// the shortest a header can be, until the first fragment's turns up
+const MIN_HEADER_LENGTH: usize = 20;
+
+// a packet with some of its fragments
+struct Partial {
+    // from the fragment at offset 0, the whole packet gets it
+    header: Option<Ipv4Header>,
+    payload: Vec<u8>,
+    received: Vec<bool>,
+    // where the payload ends, once the last fragment has turned up
+    end: Option<usize>,
+    // where the first fragment to arrive was, and how many bytes of fragments there have been
+    offset: usize,
+    length: usize,
+    started: u64,
+}
+
+impl Partial {
+    fn complete(&self) -> bool {
+        match self.end {
+            Some(end) => self.header.is_some() && self.received[..end].iter().all(|&r| r),
+            None => false,
+        }
+    }
+
+    // how much of the payload has turned up
+    fn received(&self) -> usize {
+        self.received.iter().filter(|&&r| r).count()
+    }
+}
+
+pub struct Reassembler {
+    // how long to wait for the rest of a packet after its first fragment
+    timeout: u64,
+    partials: HashMap<FragmentKey, Partial>,
+}
+
+impl Reassembler {
+    // timeout is in the same units as the times given to push
+    pub fn new(timeout: u64) -> Self {
+        Reassembler {
+            timeout,
+            partials: HashMap::new(),
+        }
+    }
+
+    // takes the next packet, which arrived at now. a packet that isn't a fragment comes straight
+    // back, a fragment comes back as the whole packet once all its pieces have arrived. packets
+    // that took too long, or whose fragments disagree about what's in them, are given up on
+    pub fn push(
+        &mut self,
+        framed: FramedPacket,
+        now: u64,
+    ) -> (Option<FramedPacket>, Vec<FramingError>) {
+        let mut errors = self.expire(now);
+        if !framed.packet.header.is_fragment() {
+            return (Some(framed), errors);
+        }
+
+        match self.add(framed, now) {
+            Ok(whole) => (whole, errors),
+            Err(error) => {
+                errors.push(error);
+                (None, errors)
+            }
+        }
+    }
+
+    // the packets still waiting for fragments, at the end of the payload
+    pub fn finish(mut self) -> Vec<FramingError> {
+        let mut errors: Vec<FramingError> = self
+            .partials
+            .drain()
+            .map(|(key, partial)| incomplete(key, partial))
+            .collect();
+        errors.sort_by_key(|error| error.offset);
+        errors
+    }
+
+    fn expire(&mut self, now: u64) -> Vec<FramingError> {
+        let timeout = self.timeout;
+        let expired: Vec<FragmentKey> = self
+            .partials
+            .iter()
+            .filter(|(_, partial)| now.saturating_sub(partial.started) > timeout)
+            .map(|(&key, _)| key)
+            .collect();
+
+        let mut errors: Vec<FramingError> = expired
+            .into_iter()
+            .filter_map(|key| Some(incomplete(key, self.partials.remove(&key)?)))
+            .collect();
+        errors.sort_by_key(|error| error.offset);
+        errors
+    }
+
+    fn add(
+        &mut self,
+        framed: FramedPacket,
+        now: u64,
+    ) -> Result<Option<FramedPacket>, FramingError> {
+        let header = &framed.packet.header;
+        let key = FragmentKey::of(header);
+        let start = header.fragment_offset as usize * 8;
+        let end = start + framed.packet.payload.len();
+        let last = !header.more_fragments();
+
+        // gives up on the fragment, and the rest of the packet if there's any of it yet
+        let give_up = |partials: &mut HashMap<FragmentKey, Partial>, kind| {
+            let (offset, length) = match partials.remove(&key) {
+                Some(partial) => (partial.offset, partial.length + framed.length),
+                None => (framed.offset, framed.length),
+            };
+            Err(FramingError {
+                offset,
+                skipped: length,
+                kind,
+            })
+        };
+
+        if !last && !(end - start).is_multiple_of(8) {
+            return give_up(
+                &mut self.partials,
+                FramingErrorKind::MisalignedFragment {
+                    identification: key.identification,
+                    length: end - start,
+                },
+            );
+        }
+
+        // the whole packet gets the first fragment's header, so that's the one that has to fit
+        // in front of the furthest fragment so far
+        let known = self.partials.get(&key);
+        let header_length = match known.and_then(|partial| partial.header.as_ref()) {
+            _ if start == 0 => header.header_length(),
+            Some(first) => first.header_length(),
+            None => MIN_HEADER_LENGTH,
+        };
+        let furthest = known.map_or(end, |partial| partial.payload.len().max(end));
+        if header_length + furthest > MAX_LENGTH {
+            return give_up(
+                &mut self.partials,
+                FramingErrorKind::FragmentTooLong {
+                    identification: key.identification,
+                    end: header_length + furthest,
+                },
+            );
+        }
+
+        let partial = self.partials.entry(key).or_insert_with(|| Partial {
+            header: None,
+            payload: Vec::new(),
+            received: Vec::new(),
+            end: None,
+            offset: framed.offset,
+            length: 0,
+            started: now,
+        });
+
+        // the same bytes sent twice are fine, different bytes for the same place aren't, and
+        // neither is anything after the end
+        let overlaps = partial.received[start.min(partial.received.len())..]
+            .iter()
+            .zip(&partial.payload[start.min(partial.payload.len())..])
+            .zip(&framed.packet.payload)
+            .any(|((&received, old), new)| received && old != new);
+        let past_end = match partial.end {
+            Some(known) => end > known || (last && end != known),
+            None => last && partial.received.iter().skip(end).any(|&r| r),
+        };
+        if overlaps || past_end {
+            return give_up(
+                &mut self.partials,
+                FramingErrorKind::OverlappingFragments {
+                    identification: key.identification,
+                },
+            );
+        }
+
+        if partial.payload.len() < end {
+            partial.payload.resize(end, 0);
+            partial.received.resize(end, false);
+        }
+        partial.payload[start..end].copy_from_slice(&framed.packet.payload);
+        partial.received[start..end]
+            .iter_mut()
+            .for_each(|r| *r = true);
+        partial.length += framed.length;
+        if last {
+            partial.end = Some(end);
+        }
+        if start == 0 {
+            partial.header = Some(framed.packet.header);
+        }
+
+        if !partial.complete() {
+            return Ok(None);
+        }
+        match self.partials.remove(&key) {
+            Some(mut partial) => match partial.header.take() {
+                Some(first) => whole(first, partial).map(Some),
+                None => Ok(None),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+// the packet the fragments add up to, with the first fragment's header made to fit it
+fn whole(first: Ipv4Header, mut partial: Partial) -> Result<FramedPacket, FramingError> {
+    let end = partial.end.unwrap_or(partial.payload.len());
+    partial.payload.truncate(end);
+    let error = |kind| FramingError {
+        offset: partial.offset,
+        skipped: partial.length,
+        kind,
+    };
+
+    let total_length = first.header_length() + end;
+    let header = Ipv4Header {
+        total_length: u16::try_from(total_length).map_err(|_| {
+            error(FramingErrorKind::FragmentTooLong {
+                identification: first.identification,
+                end: total_length,
+            })
+        })?,
+        flags: first.flags & !0b001,
+        fragment_offset: 0,
+        ..first
+    }
+    .with_checksum()
+    .map_err(|e| error(FramingErrorKind::BadHeader(e.to_string())))?;
+
+    Ok(FramedPacket {
+        offset: partial.offset,
+        length: partial.length,
+        packet: Ipv4Packet {
+            header,
+            payload: partial.payload,
+        },
+    })
+}
+
+fn incomplete(key: FragmentKey, partial: Partial) -> FramingError {
+    FramingError {
+        offset: partial.offset,
+        skipped: partial.length,
+        kind: FramingErrorKind::IncompletePacket {
+            identification: key.identification,
+            received: partial.received(),
+            expected: partial.end,
+        },
+    }
+}
+
+#[cfg(test)]
+fn fragments(data: &[u8], size: usize) -> Vec<FramedPacket> {
+    use std::net::SocketAddrV4;
+    let source = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 1);
+    let destination = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 2);
+    let packet = super::udp_datagram(source, destination, 9, data).expect("small enough");
+
+    let mut offset = 0;
+    super::fragment(&packet, size)
+        .expect("a multiple of 8")
+        .into_iter()
+        .map(|bytes| {
+            let framed = FramedPacket {
+                offset,
+                length: bytes.len(),
+                packet: super::frame(&bytes).expect("a whole packet"),
+            };
+            offset += bytes.len();
+            framed
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn reassemble(
+    reassembler: &mut Reassembler,
+    fragments: &[FramedPacket],
+) -> (Vec<FramedPacket>, Vec<FramingError>) {
+    let mut packets = Vec::new();
+    let mut errors = Vec::new();
+    for (now, fragment) in fragments.iter().enumerate() {
+        let (whole, mut more) = reassembler.push(fragment.clone(), now as u64);
+        packets.extend(whole);
+        errors.append(&mut more);
+    }
+    (packets, errors)
+}
+
+#[test]
+fn test_reassemble() {
+    let data = b"twenty four bytes of it.";
+    let pieces = fragments(data, 8);
+    assert_eq!(pieces.len(), 4);
+
+    // sent twice is fine
+    let mut reassembler = Reassembler::new(10);
+    let twice = [&pieces[..], &pieces[1..2]].concat();
+    let (packets, errors) = reassemble(&mut reassembler, &twice[1..]);
+    assert!(packets.is_empty() && errors.is_empty());
+    let (packets, errors) = reassemble(&mut reassembler, &pieces[..1]);
+    assert!(errors.is_empty());
+    assert_eq!(packets.len(), 1);
+    assert_eq!(&packets[0].packet.payload[8..], data);
+    // the copy counts too
+    assert_eq!(packets[0].length, 5 * 28);
+    assert!(packets[0].packet.header.valid_checksum());
+    assert!(reassembler.finish().is_empty());
+}
+
+#[test]
+fn test_reassembly_errors() {
+    let pieces = fragments(b"twenty four bytes of it.", 8);
+    let identification = 9;
+
+    // the same part of the packet with different bytes in it
+    let mut changed = pieces[1].clone();
+    changed.packet.payload[0] ^= 1;
+    let (packets, errors) = reassemble(
+        &mut Reassembler::new(10),
+        &[pieces[0].clone(), pieces[1].clone(), changed],
+    );
+    assert!(packets.is_empty());
+    assert_eq!(
+        errors,
+        [FramingError {
+            offset: 0,
+            skipped: 3 * 28,
+            kind: FramingErrorKind::OverlappingFragments { identification },
+        }]
+    );
+
+    // a second last fragment that ends somewhere else
+    let mut short = pieces[3].clone();
+    short.packet.payload.pop();
+    let (_, errors) = reassemble(&mut Reassembler::new(10), &[pieces[3].clone(), short]);
+    assert_eq!(
+        errors[0].kind,
+        FramingErrorKind::OverlappingFragments { identification }
+    );
+
+    // a fragment that isn't the last has to be a multiple of 8 bytes
+    let mut odd = pieces[1].clone();
+    odd.packet.payload.pop();
+    let (_, errors) = reassemble(&mut Reassembler::new(10), &[odd]);
+    assert_eq!(
+        errors[0].kind,
+        FramingErrorKind::MisalignedFragment {
+            identification,
+            length: 7
+        }
+    );
+
+    // past the end of the biggest packet there can be
+    let mut far = pieces[3].clone();
+    far.packet.header.fragment_offset = 0x1fff;
+    let (_, errors) = reassemble(&mut Reassembler::new(10), &[far]);
+    assert!(matches!(
+        errors[0].kind,
+        FramingErrorKind::FragmentTooLong { .. }
+    ));
+
+    // a last fragment that only just fits behind a 20 byte header, with a first fragment that
+    // has 40 bytes of options, whichever turns up first
+    let mut long = pieces[0].clone();
+    long.packet.header.ihl = 15;
+    let mut last = pieces[3].clone();
+    last.packet.header.fragment_offset = 8189;
+    last.packet.payload.truncate(3);
+    for order in &[[long.clone(), last.clone()], [last, long]] {
+        let (packets, errors) = reassemble(&mut Reassembler::new(10), order);
+        assert!(packets.is_empty());
+        assert_eq!(
+            errors[0].kind,
+            FramingErrorKind::FragmentTooLong {
+                identification,
+                end: 60 + 65515
+            }
+        );
+    }
+
+    // the rest takes too long
+    let mut reassembler = Reassembler::new(2);
+    let (_, errors) = reassemble(&mut reassembler, &pieces[..1]);
+    assert!(errors.is_empty());
+    let (_, errors) = reassembler.push(pieces[1].clone(), 3);
+    assert_eq!(
+        errors,
+        [FramingError {
+            offset: 0,
+            skipped: 28,
+            kind: FramingErrorKind::IncompletePacket {
+                identification,
+                received: 8,
+                expected: None
+            },
+        }]
+    );
+
+    // or never turns up
+    let (_, errors) = reassemble(&mut reassembler, &pieces[3..]);
+    assert!(errors.is_empty());
+    let errors = reassembler.finish();
+    assert_eq!(
+        errors[0].kind,
+        FramingErrorKind::IncompletePacket {
+            identification,
+            received: 16,
+            expected: Some(32)
+        }
+    );
+    assert_eq!(
+        errors[0].to_string(),
+        "skipped 56 bytes at offset 0x1c: only 16 of the 32 bytes of packet 0x0009 turned up"
+    );
+}
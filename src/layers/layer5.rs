@@ -1,38 +1,92 @@
 use super::super::ascii85;
+use super::{random_bytes, Layer};
 use anyhow::{anyhow, ensure, Result};
-use openssl::aes::{unwrap_key, AesKey};
-use openssl::symm::{decrypt, Cipher};
+use openssl::aes::{unwrap_key, wrap_key, AesKey};
+use openssl::symm::{decrypt, encrypt, Cipher};
 use std::convert::TryInto;
 
-pub fn run(bytes: &[u8]) -> Result<Vec<u8>> {
-    let bytes = ascii85::decode(bytes)?;
+// the layout peeling expects: the key encrypting key, its IV, the wrapped key, the IV for the
+// data, then the data itself
+fn encrypt_and_wrap(
+    bytes: &[u8],
+    key_encrypting_key: &[u8; 32],
+    kek_iv: [u8; 8],
+    aes_key: &[u8; 32],
+    aes_iv: &[u8; 16],
+) -> Result<Vec<u8>> {
+    let kek = AesKey::new_encrypt(key_encrypting_key).map_err(|e| anyhow!("Key error: {:?}", e))?;
+    let mut wrapped_key = [0u8; 40];
+    wrap_key(&kek, Some(kek_iv), &mut wrapped_key, aes_key)
+        .map_err(|e| anyhow!("Key error: {:?}", e))?;
+
+    let encrypted_data = encrypt(Cipher::aes_256_cbc(), aes_key, Some(aes_iv), bytes)?;
+
+    let mut out = Vec::with_capacity(96 + encrypted_data.len());
+    out.extend_from_slice(key_encrypting_key);
+    out.extend_from_slice(&kek_iv);
+    out.extend_from_slice(&wrapped_key);
+    out.extend_from_slice(aes_iv);
+    out.extend_from_slice(&encrypted_data);
+    Ok(out)
+}
+
+pub struct Layer5;
+
+impl Layer for Layer5 {
+    fn name(&self) -> &'static str {
+        "aes"
+    }
+
+    fn title(&self) -> &'static str {
+        "Advanced Encryption Standard"
+    }
 
-    ensure!(bytes.len() > 96 && bytes.len() % 8 == 0, "Invalid input");
+    fn description(&self) -> &'static str {
+        "Unwrap the AES key with the key encrypting key, then decrypt with AES-256-CBC"
+    }
 
-    let key_encrypting_key_bytes: [u8; 32] = bytes[0..32].try_into()?;
-    let key_encrypting_key = AesKey::new_decrypt(&key_encrypting_key_bytes)
+    fn peel(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let bytes = ascii85::decode(bytes)?;
+
+        ensure!(bytes.len() > 96 && bytes.len() % 8 == 0, "Invalid input");
+
+        let key_encrypting_key_bytes: [u8; 32] = bytes[0..32].try_into()?;
+        let key_encrypting_key = AesKey::new_decrypt(&key_encrypting_key_bytes)
+            .map_err(|e| anyhow!("Key error: {:?}", e))?;
+        let kek_iv: [u8; 8] = bytes[32..40].try_into()?;
+        let mut decrypted_aes_key = [0u8; 32];
+        let encrypted_aes_key = &bytes[40..80];
+
+        unwrap_key(
+            &key_encrypting_key,
+            Some(kek_iv),
+            &mut decrypted_aes_key,
+            encrypted_aes_key,
+        )
         .map_err(|e| anyhow!("Key error: {:?}", e))?;
-    let kek_iv: [u8; 8] = bytes[32..40].try_into()?;
-    let mut decrypted_aes_key = [0u8; 32];
-    let encrypted_aes_key = &bytes[40..80];
-
-    unwrap_key(
-        &key_encrypting_key,
-        Some(kek_iv),
-        &mut decrypted_aes_key,
-        &encrypted_aes_key,
-    )
-    .map_err(|e| anyhow!("Key error: {:?}", e))?;
-
-    let aes_iv: [u8; 16] = bytes[80..96].try_into()?;
-
-    let encrypted_data = &bytes[96..];
-
-    decrypt(
-        Cipher::aes_256_cbc(),
-        &decrypted_aes_key,
-        Some(&aes_iv),
-        encrypted_data,
-    )
-    .map_err(|e| anyhow!("Key error: {:?}", e))
+
+        let aes_iv: [u8; 16] = bytes[80..96].try_into()?;
+
+        let encrypted_data = &bytes[96..];
+
+        decrypt(
+            Cipher::aes_256_cbc(),
+            &decrypted_aes_key,
+            Some(&aes_iv),
+            encrypted_data,
+        )
+        .map_err(|e| anyhow!("Key error: {:?}", e))
+    }
+
+    fn wrap(&self, document: &[u8]) -> Result<Vec<u8>> {
+        let random = random_bytes(32 + 8 + 32 + 16)?;
+        let bytes = encrypt_and_wrap(
+            document,
+            random[0..32].try_into()?,
+            random[32..40].try_into()?,
+            random[40..72].try_into()?,
+            random[72..88].try_into()?,
+        )?;
+        Ok(ascii85::encode(&bytes))
+    }
 }
@@ -0,0 +1,325 @@
+use super::super::ascii85::{decode, encode};
+use super::Layer;
+use anyhow::{anyhow, bail, ensure, Result};
+use std::convert::TryInto;
+
+// register numbers as they're encoded in MV instructions. 0 is the immediate operand of MVI and
+// 7 is the memory at ptr + c, neither of which is a register
+const A: usize = 1;
+const B: usize = 2;
+const C: usize = 3;
+const F: usize = 6;
+const MEMORY: usize = 7;
+
+const PTR: usize = 5;
+const PC: usize = 6;
+
+// limits so that a program that never halts, or outputs forever, fails instead of hanging. an
+// assembled program runs at most two instructions per output byte, so it always fits
+const MAX_OUTPUT: usize = 1 << 22;
+const MAX_STEPS: usize = 2 * MAX_OUTPUT + 1;
+
+// the Tomtel Core i69
+#[derive(Debug)]
+struct Vm {
+    // a, b, c, d, e, f at 1..=6
+    registers: [u8; 7],
+    // la, lb, lc, ld, ptr, pc at 1..=6
+    registers32: [u32; 7],
+    memory: Vec<u8>,
+    output: Vec<u8>,
+}
+
+impl Vm {
+    fn new(program: &[u8]) -> Vm {
+        Vm {
+            registers: [0; 7],
+            registers32: [0; 7],
+            memory: program.to_vec(),
+            output: Vec::new(),
+        }
+    }
+
+    fn pc(&self) -> usize {
+        self.registers32[PC] as usize
+    }
+
+    fn memory_address(&self) -> usize {
+        self.registers32[PTR] as usize + self.registers[C] as usize
+    }
+
+    fn read_u8(&self, address: usize) -> Result<u8> {
+        self.memory
+            .get(address)
+            .copied()
+            .ok_or_else(|| anyhow!("read from address {:#x} is out of bounds", address))
+    }
+
+    fn read_u32(&self, address: usize) -> Result<u32> {
+        let bytes: [u8; 4] = self
+            .memory
+            .get(address..address + 4)
+            .ok_or_else(|| anyhow!("read from address {:#x} is out of bounds", address))?
+            .try_into()?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn get(&self, register: usize) -> Result<u8> {
+        match register {
+            MEMORY => self.read_u8(self.memory_address()),
+            _ => Ok(self.registers[register]),
+        }
+    }
+
+    fn set(&mut self, register: usize, value: u8) -> Result<()> {
+        match register {
+            MEMORY => {
+                let address = self.memory_address();
+                let cell = self
+                    .memory
+                    .get_mut(address)
+                    .ok_or_else(|| anyhow!("write to address {:#x} is out of bounds", address))?;
+                *cell = value;
+            }
+            _ => self.registers[register] = value,
+        }
+        Ok(())
+    }
+
+    // runs until HALT and returns everything written with OUT
+    fn run(mut self) -> Result<Vec<u8>> {
+        for _ in 0..MAX_STEPS {
+            let pc = self.pc();
+            let opcode = self.read_u8(pc)?;
+
+            // pc is moved past the whole instruction before it's executed, so jumps are absolute
+            let (length, immediate8, immediate32) = match opcode {
+                0xe1 => (2, self.read_u8(pc + 1)?, 0),
+                0x21 | 0x22 => (5, 0, self.read_u32(pc + 1)?),
+                _ if opcode >> 6 == 0b01 && opcode & 0b111 == 0 => (2, self.read_u8(pc + 1)?, 0),
+                _ if opcode >> 6 == 0b10 && opcode & 0b111 == 0 => (5, 0, self.read_u32(pc + 1)?),
+                _ => (1, 0, 0),
+            };
+            self.registers32[PC] = (pc + length) as u32;
+
+            match opcode {
+                // HALT
+                0x01 => return Ok(self.output),
+                // OUT a
+                0x02 => {
+                    ensure!(
+                        self.output.len() < MAX_OUTPUT,
+                        "output at {:#x} is longer than {} bytes",
+                        pc,
+                        MAX_OUTPUT
+                    );
+                    self.output.push(self.registers[A]);
+                }
+                // CMP
+                0xc1 => self.registers[F] = (self.registers[A] != self.registers[B]) as u8,
+                // ADD a <- b
+                0xc2 => self.registers[A] = self.registers[A].wrapping_add(self.registers[B]),
+                // SUB a <- b
+                0xc3 => self.registers[A] = self.registers[A].wrapping_sub(self.registers[B]),
+                // XOR a <- b
+                0xc4 => self.registers[A] ^= self.registers[B],
+                // APTR imm8
+                0xe1 => {
+                    self.registers32[PTR] = self.registers32[PTR].wrapping_add(immediate8 as u32)
+                }
+                // JEZ imm32
+                0x21 if self.registers[F] == 0 => self.registers32[PC] = immediate32,
+                // JNZ imm32
+                0x22 if self.registers[F] != 0 => self.registers32[PC] = immediate32,
+                0x21 | 0x22 => {}
+                // MV and MVI, 0b01DDDSSS
+                _ if opcode >> 6 == 0b01 => {
+                    let (destination, source) = operands(opcode)?;
+                    let value = match source {
+                        0 => immediate8,
+                        _ => self.get(source)?,
+                    };
+                    self.set(destination, value)?;
+                }
+                // MV32 and MVI32, 0b10DDDSSS
+                _ if opcode >> 6 == 0b10 => {
+                    let (destination, source) = operands(opcode)?;
+                    ensure!(
+                        destination <= PC && source <= PC,
+                        "invalid 32 bit register in {:#04x} at {:#x}",
+                        opcode,
+                        pc
+                    );
+                    self.registers32[destination] = match source {
+                        0 => immediate32,
+                        _ => self.registers32[source],
+                    };
+                }
+                _ => bail!("invalid instruction {:#04x} at {:#x}", opcode, pc),
+            }
+        }
+        bail!("no HALT within {} instructions", MAX_STEPS)
+    }
+}
+
+fn operands(opcode: u8) -> Result<(usize, usize)> {
+    let destination = (opcode >> 3 & 0b111) as usize;
+    let source = (opcode & 0b111) as usize;
+    ensure!(
+        destination != 0,
+        "instruction {:#04x} has no destination",
+        opcode
+    );
+    Ok((destination, source))
+}
+
+// a program that outputs the bytes one at a time, only loading a when the next byte is different
+fn assemble(output: &[u8]) -> Vec<u8> {
+    let mut program = Vec::with_capacity(output.len() * 3 + 1);
+    let mut a = 0;
+
+    for &byte in output {
+        if byte != a {
+            // MVI a <- byte
+            program.extend_from_slice(&[0x48, byte]);
+            a = byte;
+        }
+        // OUT a
+        program.push(0x02);
+    }
+    // HALT
+    program.push(0x01);
+    program
+}
+
+pub struct Layer6;
+
+impl Layer for Layer6 {
+    fn name(&self) -> &'static str {
+        "vm"
+    }
+
+    fn title(&self) -> &'static str {
+        "Virtual Machine"
+    }
+
+    fn description(&self) -> &'static str {
+        "Run the payload as a program on the Tomtel Core i69 virtual machine"
+    }
+
+    fn peel(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let program = decode(bytes)?;
+        Vm::new(&program).run()
+    }
+
+    fn wrap(&self, document: &[u8]) -> Result<Vec<u8>> {
+        ensure!(
+            document.len() <= MAX_OUTPUT,
+            "document is longer than {} bytes",
+            MAX_OUTPUT
+        );
+        Ok(encode(&assemble(document)))
+    }
+}
+
+#[cfg(test)]
+const HELLO_WORLD: [u8; 82] = [
+    0x50, 0x48, // MVI b <- 72
+    0xC2, // ADD a <- b
+    0x02, // OUT a
+    0xA8, 0x4D, 0x00, 0x00, 0x00, // MVI32 ptr <- 0x0000004d
+    0x4F, // MV a <- (ptr+c)
+    0x02, // OUT a
+    0x50, 0x09, // MVI b <- 9
+    0xC4, // XOR a <- b
+    0x02, // OUT a
+    0x02, // OUT a
+    0xE1, 0x01, // APTR 0x00000001
+    0x4F, // MV a <- (ptr+c)
+    0x02, // OUT a
+    0xC1, // CMP
+    0x22, 0x1D, 0x00, 0x00, 0x00, // JNZ 0x0000001d
+    0x48, 0x30, // MVI a <- 48
+    0x02, // OUT a
+    0x58, 0x03, // MVI c <- 3
+    0x4F, // MV a <- (ptr+c)
+    0x02, // OUT a
+    0xB0, 0x29, 0x00, 0x00, 0x00, // MVI32 pc <- 0x00000029
+    0x48, 0x31, // MVI a <- 49
+    0x02, // OUT a
+    0x50, 0x0C, // MVI b <- 12
+    0xC3, // SUB a <- b
+    0x02, // OUT a
+    0xAA, // MV32 ptr <- lb
+    0x57, // MV b <- (ptr+c)
+    0x48, 0x02, // MVI a <- 2
+    0xC1, // CMP
+    0x21, 0x3A, 0x00, 0x00, 0x00, // JEZ 0x0000003a
+    0x48, 0x32, // MVI a <- 50
+    0x02, // OUT a
+    0x48, 0x77, // MVI a <- 119
+    0x02, // OUT a
+    0x48, 0x6F, // MVI a <- 111
+    0x02, // OUT a
+    0x48, 0x72, // MVI a <- 114
+    0x02, // OUT a
+    0x48, 0x6C, // MVI a <- 108
+    0x02, // OUT a
+    0x48, 0x64, // MVI a <- 100
+    0x02, // OUT a
+    0x48, 0x21, // MVI a <- 33
+    0x02, // OUT a
+    0x01, // HALT
+    0x65, 0x6F, 0x33, 0x34, 0x2C, // non-instruction data
+];
+
+#[test]
+fn test_hello_world() -> Result<()> {
+    // the example program from the layer 6 instructions
+    let output = Vm::new(&HELLO_WORLD).run()?;
+    assert_eq!(String::from_utf8(output)?, "Hello, world!");
+    Ok(())
+}
+
+#[test]
+fn test_peel() -> Result<()> {
+    let payload = super::super::ascii85::encode(&HELLO_WORLD);
+    assert_eq!(Layer6.peel(&payload)?, b"Hello, world!");
+    Ok(())
+}
+
+#[test]
+fn test_assemble() -> Result<()> {
+    let output = b"\0\0Hello, world!\n\n";
+    assert_eq!(Vm::new(&assemble(output)).run()?, output);
+    Ok(())
+}
+
+#[test]
+fn test_invalid_programs() {
+    // runs off the end without halting
+    assert!(Vm::new(&[0x02]).run().is_err());
+    // 0x00 isn't an instruction
+    assert!(Vm::new(&[0x00]).run().is_err());
+    // MV32 with register 7 as the destination
+    assert!(Vm::new(&[0b10_111_001, 0x01]).run().is_err());
+    // MV a <- (ptr+c) with ptr pointing nowhere
+    assert!(Vm::new(&[0xa8, 0xff, 0xff, 0x00, 0x00, 0x4f, 0x01])
+        .run()
+        .is_err());
+    // MVI32 pc <- 0, forever
+    assert!(Vm::new(&[0xb0, 0x00, 0x00, 0x00, 0x00]).run().is_err());
+    // OUT a, then MVI32 pc <- 0, forever
+    assert!(Vm::new(&[0x02, 0xb0, 0x00, 0x00, 0x00, 0x00])
+        .run()
+        .is_err());
+}
+
+#[test]
+fn test_assemble_longest() -> Result<()> {
+    // the longest document that can be wrapped still peels, with every byte loaded separately
+    let output: Vec<u8> = (0..MAX_OUTPUT).map(|i| (i % 2 + 1) as u8).collect();
+    assert_eq!(Vm::new(&assemble(&output)).run()?, output);
+    assert!(Layer6.wrap(&vec![0; MAX_OUTPUT + 1]).is_err());
+    Ok(())
+}
@@ -4,35 +4,297 @@ pub mod layer2;
 pub mod layer3;
 pub mod layer4;
 pub mod layer5;
+pub mod layer6;
 
-use anyhow::{anyhow, Result};
-use std::fs::File;
-use std::io::prelude::*;
-use std::path::PathBuf;
+use super::transform::Transform;
+use super::xor::{Crib, Keystream};
+use anyhow::{anyhow, ensure, Context, Result};
+use layer4::filter::Filter;
+use openssl::rand::rand_bytes;
+use std::fs::{self, File};
+use std::io::{self, prelude::*};
+use std::path::Path;
+
+pub trait Layer {
+    // short name for picking a layer out, e.g. from the command line
+    fn name(&self) -> &'static str;
+    // the title in the layer's document header
+    fn title(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    // turns a layer's payload into the next layer's document
+    fn peel(&self, input: &[u8]) -> Result<Vec<u8>>;
+    // peel, along with anything whoever's peeling would want to know, like a key that had to be
+    // recovered. most layers have nothing to say
+    fn peel_with_diagnostics(&self, input: &[u8]) -> Result<(Vec<u8>, Vec<String>)> {
+        Ok((self.peel(input)?, Vec::new()))
+    }
+    // the other direction, turns the next layer's document into this layer's payload. anything
+    // that peeling doesn't need to be told (keys, noise packets) is picked at random
+    fn wrap(&self, document: &[u8]) -> Result<Vec<u8>>;
+    // the layer set up with options from the command line, None if none of them are for it
+    fn with_options(&self, _options: &LayerOptions) -> Option<Box<dyn Layer>> {
+        None
+    }
+}
+
+// changes to how layers peel
+#[derive(Debug, Default, PartialEq)]
+pub struct LayerOptions {
+    // replaces layer 1's transform
+    pub transform: Option<Transform>,
+    // for recovering the layer 3 key
+    pub cribs: Vec<Crib>,
+    pub key_length: Option<usize>,
+    // so layer 3 doesn't have to recover it
+    pub key: Option<Keystream>,
+    // replaces layer 4's filter
+    pub filter: Option<Filter>,
+}
+
+// in the order they have to be peeled, the index is the layer number
+pub static LAYERS: &[&(dyn Layer + Sync)] = &[
+    &layer0::Layer0,
+    &layer1::Layer1::DEFAULT,
+    &layer2::Layer2,
+    &layer3::Layer3::DEFAULT,
+    &layer4::Layer4::DEFAULT,
+    &layer5::Layer5,
+    &layer6::Layer6,
+];
+
+pub fn by_name(name: &str) -> Option<&'static (dyn Layer + Sync)> {
+    LAYERS.iter().copied().find(|layer| layer.name() == name)
+}
+
+// peeling layer N gives the document for layer N + 1, unless there's nothing left to peel.
+// version 1.0 of the onion ends after layer 5, later versions go on to layer 6
+pub fn output_file_name(layer: usize, document: &str) -> String {
+    if find_input(document).is_ok() {
+        format!("layer_{}.txt", layer + 1)
+    } else {
+        String::from("the_core.txt")
+    }
+}
+
+const PAYLOAD_HEADER: &str = "==[ Payload ]===============================================";
+const HEADER_WIDTH: usize = 60;
 
 pub fn find_input(haystack: &str) -> Result<Vec<u8>> {
-    let needle = "==[ Payload ]===============================================";
     haystack
-        .find(needle)
+        .find(PAYLOAD_HEADER)
         .map(|idx| haystack[idx..].trim().as_bytes().to_vec())
-        .ok_or_else(|| anyhow!("Couldn't find payload delimeter: {}", needle))
+        .ok_or_else(|| anyhow!("Couldn't find payload delimeter: {}", PAYLOAD_HEADER))
 }
 
-pub fn write_output(path: &str, bytes: &[u8]) -> Result<()> {
-    let mut output_path = PathBuf::from("out");
-    output_path.push(path);
-    let mut f = File::create(output_path)?;
-    f.write_all(bytes)?;
+// the document for layer N, the one you get from peeling layer N - 1
+fn document(layer: usize, last_layer: usize, payload: &[u8]) -> Vec<u8> {
+    let title = format!(
+        "==[ Layer {}/{}: {} ]",
+        layer,
+        last_layer,
+        LAYERS[layer].title()
+    );
+    let mut document = format!(
+        "{:=<width$}\n\n{}\n\n{}\n\n",
+        title,
+        LAYERS[layer].description(),
+        PAYLOAD_HEADER,
+        width = HEADER_WIDTH
+    )
+    .into_bytes();
+    document.extend_from_slice(payload);
+    document.push(b'\n');
+    document
+}
 
-    Ok(())
+// wraps the core in layers 0 to last_layer, the result is ready to be peeled from layer 0
+pub fn build(core: &[u8], last_layer: usize) -> Result<Vec<u8>> {
+    ensure!(
+        last_layer < LAYERS.len(),
+        "there are only layers 0 to {}",
+        LAYERS.len() - 1
+    );
+
+    let mut document = core.to_vec();
+    for layer in (1..=last_layer).rev() {
+        let payload = LAYERS[layer].wrap(&document)?;
+        document = self::document(layer, last_layer, &payload);
+    }
+    LAYERS[0].wrap(&document)
+}
+
+pub fn random_bytes(len: usize) -> Result<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    rand_bytes(&mut bytes)?;
+    Ok(bytes)
 }
 
-pub fn read_initial_input() -> Result<Vec<u8>> {
-    let mut f = File::open("input.txt")?;
-    let mut buffer = f
-        .metadata()
-        .map_or_else(|_| Vec::new(), |m| Vec::with_capacity(m.len() as usize));
+pub fn write_output(dir: &Path, name: &str, bytes: &[u8]) -> Result<()> {
+    let path = dir.join(name);
+    fs::create_dir_all(dir)
+        .and_then(|_| File::create(&path)?.write_all(bytes))
+        .with_context(|| format!("couldn't write {}", path.display()))
+}
+
+// - reads stdin
+pub fn read_input(path: &Path) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    if path == Path::new("-") {
+        io::stdin().read_to_end(&mut buffer)?;
+        return Ok(buffer);
+    }
 
-    f.read_to_end(&mut buffer)?;
+    let mut f = File::open(path).with_context(|| format!("couldn't open {}", path.display()))?;
+    if let Ok(metadata) = f.metadata() {
+        buffer.reserve(metadata.len() as usize);
+    }
+    f.read_to_end(&mut buffer)
+        .with_context(|| format!("couldn't read {}", path.display()))?;
     Ok(buffer)
 }
+
+#[test]
+fn test_layer_names_are_unique() {
+    for (i, layer) in LAYERS.iter().enumerate() {
+        assert!(!layer.description().is_empty());
+        assert_eq!(
+            by_name(layer.name()).map(|found| found.description()),
+            Some(layer.description()),
+            "layer {} shares its name with another layer",
+            i
+        );
+    }
+}
+
+#[test]
+fn test_with_options() -> Result<()> {
+    let none = LayerOptions::default();
+    assert!(LAYERS
+        .iter()
+        .all(|layer| layer.with_options(&none).is_none()));
+
+    let options = LayerOptions {
+        key: Some("5a".parse()?),
+        ..LayerOptions::default()
+    };
+    let layer3 = LAYERS[3]
+        .with_options(&options)
+        .expect("layer 3 takes a key");
+    let (document, diagnostics) = layer3.peel_with_diagnostics(&layer3.wrap(b"document")?)?;
+    assert_eq!(document, b"document");
+    assert_eq!(diagnostics, ["key 5a"]);
+
+    let options = LayerOptions {
+        filter: Some("dst port 1".parse()?),
+        ..LayerOptions::default()
+    };
+    let layer4 = LAYERS[4]
+        .with_options(&options)
+        .expect("layer 4 takes a filter");
+    let (document, diagnostics) = layer4.peel_with_diagnostics(&LAYERS[4].wrap(b"document")?)?;
+    assert!(document.is_empty());
+    assert!(diagnostics.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_peel_every_layer() -> Result<()> {
+    let mut input = read_input(Path::new("input.txt"))?;
+    let mut document = String::new();
+
+    for layer in LAYERS {
+        document = String::from_utf8(layer.peel(&input)?)?;
+        match find_input(&document) {
+            Ok(payload) => input = payload,
+            Err(_) => break,
+        }
+    }
+
+    assert!(document.starts_with("==[ The Core ]"));
+    Ok(())
+}
+
+#[test]
+fn test_build() -> Result<()> {
+    let core = b"==[ The Core ]==============================================\n\nhello\n";
+    let mut input = build(core, LAYERS.len() - 1)?;
+
+    for layer in LAYERS {
+        let document = layer.peel(&input)?;
+        match find_input(&String::from_utf8(document.clone())?) {
+            Ok(payload) => input = payload,
+            Err(_) => {
+                assert_eq!(document, core);
+                return Ok(());
+            }
+        }
+    }
+    panic!("peeled every layer without finding the core");
+}
+
+// real documents have a couple of kilobytes of text. much less and there aren't enough letters
+// under each key byte to tell which one is right
+#[cfg(test)]
+const XOR_TEXT: &str = "\
+When computers send data over a network like the internet, the data is broken up and placed
+within packets. As well as containing the data being sent, packets contain extra data like the
+destination address (where the packet should be sent to), and the source address (where the
+packet came from). This extra information allows the packets to be relayed between many
+different routers -- travelling across land, air, ocean, and maybe even outer space -- all
+cooperating to send the packet to its intended destination.
+
+The payload for this layer is a stream of packets, one after the other. Each one starts with the
+headers that say where it came from and where it is going, followed by the data it carries. Not
+every packet is one you want though. Some of them were sent from the wrong address, some of them
+were sent to the wrong port, and some of them were damaged on the way, which you can tell from
+their checksums. Throw those away and join the data from the rest together, in the order they
+arrived, to get the document for the next layer.";
+
+#[cfg(test)]
+fn round_trip(layer: &dyn Layer, document: &[u8]) -> Vec<u8> {
+    let payload = layer.wrap(document).expect("wrapping never fails");
+    layer
+        .peel(&payload)
+        .expect("a wrapped document can be peeled")
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_round_trip(document in proptest::collection::vec(0..=255u8, 0..1024)) {
+        for layer in &[
+            &layer0::Layer0 as &dyn Layer,
+            &layer1::Layer1::DEFAULT,
+            &layer4::Layer4::DEFAULT,
+            &layer5::Layer5,
+            &layer6::Layer6,
+        ] {
+            proptest::prop_assert_eq!(&round_trip(*layer, &document), &document, "{}", layer.name());
+        }
+    }
+
+    // parity can't record the length, so it comes back padded to a multiple of 7 with newlines
+    #[test]
+    fn test_parity_round_trip(document in proptest::collection::vec(0..=255u8, 0..1024)) {
+        let peeled = round_trip(&layer2::Layer2, &document);
+        proptest::prop_assert!(peeled.len().is_multiple_of(7) && peeled.len() < document.len() + 7);
+        proptest::prop_assert_eq!(&peeled[..document.len()], &document[..]);
+        proptest::prop_assert!(peeled[document.len()..].iter().all(|&b| b == b'\n'));
+    }
+
+    // the xor layer finds its key by frequency analysis, so it needs a document that looks like
+    // one, with any title and payload
+    #[test]
+    fn test_xor_round_trip(
+        title in "[A-Za-z ]{1,40}",
+        payload in proptest::collection::vec(0..=255u8, 0..1024),
+    ) {
+        let mut document = format!(
+            "{:=<60}\n\n{}\n\n",
+            format!("==[ Layer 4/6: {} ]", title),
+            XOR_TEXT,
+        ).into_bytes();
+        document.extend_from_slice(&super::ascii85::encode(&payload));
+        proptest::prop_assert_eq!(round_trip(&layer3::Layer3::DEFAULT, &document), document);
+    }
+}
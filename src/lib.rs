@@ -0,0 +1,10 @@
+pub mod ascii85;
+pub mod bits;
+pub mod cli;
+pub mod cribdrag;
+pub mod discover;
+pub mod layers;
+pub mod parity;
+pub mod score;
+pub mod transform;
+pub mod xor;
@@ -1,33 +1,252 @@
-mod ascii85;
-mod layers;
-use anyhow::Result;
+use anyhow::Error;
+use std::io::{self, prelude::*};
+use std::path::Path;
+use std::process;
 
-use layers::*;
+use onion::ascii85;
+use onion::cli::{self, Command, LayerOptions};
+use onion::cribdrag::{self, CribDrag};
+use onion::discover::discover;
+use onion::layers::*;
+use onion::parity::StripOptions;
 
-fn main() -> Result<()> {
-    let layer0_input = read_initial_input()?;
-    let layer1 = layer0::run(&layer0_input)?;
-    write_output("layer_1.txt", &layer1)?;
+// exit codes from sysexits.h
+const EX_USAGE: i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_IOERR: i32 = 74;
 
-    let layer1input = find_input(&String::from_utf8(layer1)?)?;
-    let layer2 = layer1::run(&layer1input)?;
-    write_output("layer_2.txt", &layer2)?;
+// keeps track of what went wrong so it can be turned into an exit code
+enum Failure {
+    Io(Error),
+    Decode(Error),
+}
+
+type Result<T> = std::result::Result<T, Failure>;
 
-    let layer2input = find_input(&String::from_utf8(layer2)?)?;
-    let layer3 = layer2::run(&layer2input)?;
-    write_output("layer_3.txt", &layer3)?;
+fn io_failure<T>(result: anyhow::Result<T>) -> Result<T> {
+    result.map_err(Failure::Io)
+}
 
-    let layer3input = find_input(&String::from_utf8(layer3)?)?;
-    let layer4 = layer3::run(&layer3input)?;
-    write_output("layer_4.txt", &layer4[..layer4.len() - 1])?;
+fn decode_failure<T>(result: anyhow::Result<T>) -> Result<T> {
+    result.map_err(Failure::Decode)
+}
 
-    let layer4input = find_input(&String::from_utf8(layer4)?)?;
-    let layer5 = layer4::run(&layer4input)?;
-    write_output("layer_5.txt", &layer5)?;
+fn write_stdout(bytes: &[u8]) -> Result<()> {
+    let mut stdout = io::stdout();
+    io_failure(
+        stdout
+            .write_all(bytes)
+            .and_then(|_| stdout.flush())
+            .map_err(Error::from),
+    )
+}
 
-    let layer5input = find_input(&String::from_utf8(layer5)?)?;
-    let core = layer5::run(&layer5input)?;
-    write_output("the_core.txt", &core)?;
+// LAYERS, with any that were set up differently from the command line swapped in
+struct Layers {
+    configured: Vec<Option<Box<dyn Layer>>>,
+}
 
+impl Layers {
+    fn new(options: LayerOptions) -> Self {
+        Layers {
+            configured: LAYERS
+                .iter()
+                .map(|layer| layer.with_options(&options))
+                .collect(),
+        }
+    }
+
+    fn get(&self, i: usize) -> &dyn Layer {
+        match &self.configured[i] {
+            Some(layer) => layer.as_ref(),
+            None => LAYERS[i],
+        }
+    }
+
+    // logs whatever the layer had to say, like the layer 3 key or anything layer 4 couldn't read
+    // as packets
+    fn peel(&self, i: usize, bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let (document, diagnostics) = self.get(i).peel_with_diagnostics(bytes)?;
+        for diagnostic in diagnostics {
+            eprintln!("layer {}: {}", i, diagnostic);
+        }
+        Ok(document)
+    }
+}
+
+// the input can be a whole document from a previous run or just a payload
+fn payload(document: Vec<u8>) -> Vec<u8> {
+    String::from_utf8(document.clone())
+        .ok()
+        .and_then(|document| find_input(&document).ok())
+        .unwrap_or(document)
+}
+
+fn peel(
+    input: &Path,
+    out: &Path,
+    from_layer: usize,
+    to_layer: usize,
+    stdout: bool,
+    layers: Layers,
+) -> Result<()> {
+    let mut input = payload(io_failure(read_input(input))?);
+    let mut document = String::new();
+
+    for i in from_layer..=to_layer {
+        let peeled = decode_failure(layers.peel(i, &input))?;
+        document = decode_failure(String::from_utf8(peeled).map_err(Error::from))?;
+        if !stdout {
+            io_failure(write_output(
+                out,
+                &output_file_name(i, &document),
+                document.as_bytes(),
+            ))?;
+        }
+
+        match find_input(&document) {
+            Ok(payload) => input = payload,
+            // reached the core
+            Err(_) => break,
+        }
+    }
+
+    if stdout {
+        write_stdout(document.as_bytes())?;
+    }
     Ok(())
 }
+
+fn run(command: Command) -> Result<()> {
+    match command {
+        Command::Peel {
+            input,
+            out,
+            from_layer,
+            to_layer,
+            stdout,
+            options,
+        } => {
+            let layers = Layers::new(options);
+            peel(&input, &out, from_layer, to_layer, stdout, layers)
+        }
+        Command::DecodeAscii85 { input } => {
+            let encoded = io_failure(read_input(&input))?;
+            let decoded = decode_failure(ascii85::decode(&encoded).map_err(Error::from))?;
+            write_stdout(&decoded)
+        }
+        Command::Layer {
+            layer: i,
+            input,
+            options,
+        } => {
+            let payload = payload(io_failure(read_input(&input))?);
+            let layers = Layers::new(options);
+            let peeled = decode_failure(layers.peel(i, &payload))?;
+            write_stdout(&peeled)
+        }
+        Command::Build { core, to_layer } => {
+            let core = io_failure(read_input(&core))?;
+            let onion = decode_failure(build(&core, to_layer))?;
+            write_stdout(&onion)
+        }
+        Command::ParityReport { input, pad } => {
+            let payload = io_failure(read_input(&input))?;
+            let bytes = decode_failure(ascii85::decode(&payload).map_err(Error::from))?;
+            let options = StripOptions {
+                pad_final_group: pad,
+            };
+            let (_, report) = decode_failure(layer2::combine_with_report(&bytes, options))?;
+            write_stdout(report.to_string().as_bytes())
+        }
+        Command::Discover { input, prefix } => {
+            let payload = payload(io_failure(read_input(&input))?);
+            let bytes = decode_failure(ascii85::decode(&payload).map_err(Error::from))?;
+            let candidates = discover(&bytes, prefix.as_bytes());
+            if candidates.is_empty() {
+                return Err(Failure::Decode(anyhow::anyhow!(
+                    "no transform turns the payload into text starting with {:?}",
+                    prefix
+                )));
+            }
+
+            let mut report = String::new();
+            for candidate in candidates {
+                let first_line = candidate
+                    .output
+                    .split(|&b| b == b'\n')
+                    .next()
+                    .unwrap_or(&[]);
+                report += &format!(
+                    "{:.4}  {:<24}  {}\n",
+                    candidate.score,
+                    candidate.transform.to_string(),
+                    String::from_utf8_lossy(first_line)
+                );
+            }
+            write_stdout(report.as_bytes())
+        }
+        Command::CribDrag { input, key_length } => {
+            let payload = payload(io_failure(read_input(&input))?);
+            let bytes = decode_failure(ascii85::decode(&payload).map_err(Error::from))?;
+            let key_length = match key_length {
+                Some(key_length) => key_length,
+                None => decode_failure(
+                    onion::xor::guess_key_length(&bytes, layer3::MAX_KEY_LENGTH).ok_or_else(|| {
+                        anyhow::anyhow!("there isn't enough to guess the key length from")
+                    }),
+                )?,
+            };
+            let mut state = decode_failure(CribDrag::new(bytes, key_length))?;
+            io_failure(cribdrag::run(&mut state, io::stdin().lock(), io::stdout()))
+        }
+        Command::ToPcap { input, pcapng } => {
+            let payload = payload(io_failure(read_input(&input))?);
+            let (captured, errors) = decode_failure(layer4::Layer4::DEFAULT.capture(&payload))?;
+            for error in errors {
+                eprintln!("layer 4: {}", error);
+            }
+            let capture = match pcapng {
+                true => layer4::pcap::write_pcapng(&captured),
+                false => layer4::pcap::write_pcap(&captured),
+            };
+            write_stdout(&capture)
+        }
+        Command::FromPcap { input, filter } => {
+            let capture = io_failure(read_input(&input))?;
+            let layer4 = layer4::Layer4::new(filter.unwrap_or_default());
+            let (document, errors) = decode_failure(layer4.peel_capture(&capture))?;
+            for error in errors {
+                eprintln!("layer 4: {}", error);
+            }
+            write_stdout(&document)
+        }
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match cli::parse(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            process::exit(EX_USAGE);
+        }
+    };
+
+    match run(command) {
+        Ok(()) => {}
+        Err(Failure::Io(e)) => {
+            eprintln!("error: {:#}", e);
+            process::exit(EX_IOERR);
+        }
+        Err(Failure::Decode(e)) => {
+            eprintln!("error: {:#}", e);
+            process::exit(EX_DATAERR);
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use super::bits::{BitOrder, BitReader, BitWriter};
+use anyhow::{ensure, Result};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Parity {
+    // the parity bit makes the number of ones in a word even
+    Even,
+    // or odd
+    Odd,
+}
+
+// where the parity bit sits in a word, the data bits fill the rest
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitPosition {
+    Lsb,
+    Msb,
+}
+
+// each word is data_bits + 1 bits, stored big-endian in as few whole bytes as fit. any bits above
+// that are ignored when reading and zero when writing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParityScheme {
+    pub parity: Parity,
+    pub position: BitPosition,
+    pub data_bits: u32,
+}
+
+pub fn popcount(n: u32) -> u32 {
+    n.count_ones()
+}
+
+fn mask(bits: u32) -> u32 {
+    match bits {
+        32 => u32::MAX,
+        _ => (1 << bits) - 1,
+    }
+}
+
+impl ParityScheme {
+    pub fn word_bits(&self) -> u32 {
+        self.data_bits + 1
+    }
+
+    pub fn word_bytes(&self) -> usize {
+        (self.word_bits() as usize).div_ceil(8)
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            (1..=31).contains(&self.data_bits),
+            "parity words can have 1 to 31 data bits, not {}",
+            self.data_bits
+        );
+        Ok(())
+    }
+
+    // the parity bit that goes with these data bits
+    pub fn parity_bit(&self, data: u32) -> bool {
+        let odd_ones = popcount(data & mask(self.data_bits)) % 2 == 1;
+        match self.parity {
+            Parity::Even => odd_ones,
+            Parity::Odd => !odd_ones,
+        }
+    }
+
+    pub fn check(&self, word: u32) -> bool {
+        let odd_ones = popcount(word & mask(self.word_bits())) % 2 == 1;
+        match self.parity {
+            Parity::Even => !odd_ones,
+            Parity::Odd => odd_ones,
+        }
+    }
+
+    pub fn data(&self, word: u32) -> u32 {
+        match self.position {
+            BitPosition::Lsb => (word >> 1) & mask(self.data_bits),
+            BitPosition::Msb => word & mask(self.data_bits),
+        }
+    }
+
+    pub fn word(&self, data: u32) -> u32 {
+        let data = data & mask(self.data_bits);
+        let bit = self.parity_bit(data) as u32;
+        match self.position {
+            BitPosition::Lsb => data << 1 | bit,
+            BitPosition::Msb => bit << self.data_bits | data,
+        }
+    }
+
+    fn read_word(&self, bytes: &[u8]) -> u32 {
+        bytes.iter().fold(0, |word, &byte| word << 8 | byte as u32)
+    }
+
+    fn write_word(&self, word: u32, out: &mut Vec<u8>) {
+        let bytes = word.to_be_bytes();
+        out.extend_from_slice(&bytes[4 - self.word_bytes()..]);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiscardedWord {
+    // of the first byte of the word in the input
+    pub offset: usize,
+    pub value: u32,
+}
+
+// what strip_parity threw away, for working out what went wrong with a corrupted payload
+#[derive(Debug, Default, PartialEq)]
+pub struct ParityReport {
+    pub kept: usize,
+    pub discarded: Vec<DiscardedWord>,
+    // zero bits added after the last good word to finish the last byte
+    pub padding_bits: u32,
+}
+
+impl ParityReport {
+    pub fn total(&self) -> usize {
+        self.kept + self.discarded.len()
+    }
+}
+
+impl fmt::Display for ParityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "kept {} of {} words, discarded {} with bad parity",
+            self.kept,
+            self.total(),
+            self.discarded.len()
+        )?;
+        if self.padding_bits > 0 {
+            writeln!(
+                f,
+                "padded the last byte with {} zero bits",
+                self.padding_bits
+            )?;
+        }
+        for word in &self.discarded {
+            writeln!(f, "  offset {:#x}: {:#04x}", word.offset, word.value)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StripOptions {
+    // when the good words don't add up to whole bytes, finish the last byte with zero bits
+    // instead of failing
+    pub pad_final_group: bool,
+}
+
+// drops the words with bad parity and packs the data bits of the rest into bytes. the data bits
+// have to add up to whole bytes
+pub fn strip_parity(bytes: &[u8], scheme: ParityScheme) -> Result<Vec<u8>> {
+    strip_parity_with_report(bytes, scheme, StripOptions::default()).map(|(out, _)| out)
+}
+
+pub fn strip_parity_with_report(
+    bytes: &[u8],
+    scheme: ParityScheme,
+    options: StripOptions,
+) -> Result<(Vec<u8>, ParityReport)> {
+    scheme.validate()?;
+    ensure!(
+        bytes.len().is_multiple_of(scheme.word_bytes()),
+        "{} bytes left over after the last {} byte word",
+        bytes.len() % scheme.word_bytes(),
+        scheme.word_bytes()
+    );
+
+    let mut out = BitWriter::with_capacity(bytes.len(), BitOrder::MsbFirst, scheme.data_bits)?;
+    let mut report = ParityReport::default();
+
+    for (i, word) in bytes.chunks(scheme.word_bytes()).enumerate() {
+        let word = scheme.read_word(word);
+        if !scheme.check(word) {
+            report.discarded.push(DiscardedWord {
+                offset: i * scheme.word_bytes(),
+                value: word,
+            });
+            continue;
+        }
+
+        report.kept += 1;
+        out.write(scheme.data(word));
+    }
+
+    if out.pending_bits() > 0 {
+        ensure!(
+            options.pad_final_group,
+            "the words with good parity leave {} bits that don't fill a byte ({} of {} words \
+             discarded)",
+            out.pending_bits(),
+            report.discarded.len(),
+            report.total()
+        );
+        report.padding_bits = 8 - out.pending_bits();
+    }
+
+    Ok((out.finish(), report))
+}
+
+// splits the bytes into data_bits sized pieces and gives each one a parity bit. the bytes have to
+// split evenly
+pub fn add_parity(bytes: &[u8], scheme: ParityScheme) -> Result<Vec<u8>> {
+    scheme.validate()?;
+    let total_bits = bytes.len() as u64 * 8;
+    ensure!(
+        total_bits.is_multiple_of(scheme.data_bits as u64),
+        "{} bytes don't split into {} bit pieces",
+        bytes.len(),
+        scheme.data_bits
+    );
+
+    let word_count = (total_bits / scheme.data_bits as u64) as usize;
+    let mut out = Vec::with_capacity(word_count * scheme.word_bytes());
+    for data in BitReader::new(bytes, BitOrder::MsbFirst, scheme.data_bits)? {
+        scheme.write_word(scheme.word(data), &mut out);
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+const SEVEN_BIT_EVEN_LSB: ParityScheme = ParityScheme {
+    parity: Parity::Even,
+    position: BitPosition::Lsb,
+    data_bits: 7,
+};
+
+#[test]
+fn test_popcount() {
+    assert_eq!(0, popcount(0b0000_0000));
+    assert_eq!(1, popcount(0b0000_0010));
+    assert_eq!(1, popcount(0b0100_0000));
+    assert_eq!(7, popcount(0b1111_1110));
+    // bit 0 counts too
+    assert_eq!(1, popcount(0b0000_0001));
+    assert_eq!(8, popcount(0b1111_1111));
+}
+
+#[test]
+fn test_parity_bit() {
+    let scheme = SEVEN_BIT_EVEN_LSB;
+    assert!(!scheme.parity_bit(0b0000_0000 >> 1));
+    assert!(scheme.parity_bit(0b0000_0010 >> 1));
+    assert!(!scheme.parity_bit(0b0010_0010 >> 1));
+    assert!(scheme.parity_bit(0b0100_0000 >> 1));
+    assert!(scheme.parity_bit(0b1111_1110 >> 1));
+
+    let odd = ParityScheme {
+        parity: Parity::Odd,
+        ..scheme
+    };
+    assert!(odd.parity_bit(0b000_0000));
+    assert!(!odd.parity_bit(0b000_0001));
+}
+
+#[test]
+fn test_check() {
+    let scheme = SEVEN_BIT_EVEN_LSB;
+    assert!(scheme.check(0b1011_0010));
+    assert!(scheme.check(0b0000_0000));
+    assert!(scheme.check(0b1111_1111));
+    assert!(!scheme.check(0b0000_0001));
+
+    let msb = ParityScheme {
+        position: BitPosition::Msb,
+        ..scheme
+    };
+    assert_eq!(msb.word(0b000_0001), 0b1000_0001);
+    assert_eq!(msb.data(0b1000_0001), 0b000_0001);
+    assert_eq!(scheme.word(0b000_0001), 0b0000_0011);
+    assert_eq!(scheme.data(0b0000_0011), 0b000_0001);
+}
+
+#[test]
+fn test_strip_parity() -> Result<()> {
+    let scheme = SEVEN_BIT_EVEN_LSB;
+    let words = add_parity(b"parity!", scheme)?;
+    assert_eq!(words.len(), 8);
+
+    // a word with bad parity in the middle gets dropped
+    let mut noisy = words.clone();
+    noisy.insert(3, 0b0000_0001);
+    assert_eq!(strip_parity(&noisy, scheme)?, b"parity!");
+
+    // 7 good words are 49 bits
+    assert!(strip_parity(&words[..7], scheme).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_report() -> Result<()> {
+    let scheme = SEVEN_BIT_EVEN_LSB;
+    let mut words = add_parity(b"parity!", scheme)?;
+    words.insert(0, 0b0000_0001);
+    words.insert(5, 0b1111_1110);
+
+    let (out, report) = strip_parity_with_report(&words, scheme, StripOptions::default())?;
+    assert_eq!(out, b"parity!");
+    assert_eq!(report.kept, 8);
+    assert_eq!(report.total(), 10);
+    assert_eq!(
+        report.discarded,
+        vec![
+            DiscardedWord {
+                offset: 0,
+                value: 0b0000_0001
+            },
+            DiscardedWord {
+                offset: 5,
+                value: 0b1111_1110
+            },
+        ]
+    );
+    assert_eq!(report.padding_bits, 0);
+    assert_eq!(
+        report.to_string(),
+        "kept 8 of 10 words, discarded 2 with bad parity\n  offset 0x0: 0x01\n  offset 0x5: 0xfe\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_pad_final_group() -> Result<()> {
+    let scheme = SEVEN_BIT_EVEN_LSB;
+    let words = add_parity(b"parity!", scheme)?;
+    let pad = StripOptions {
+        pad_final_group: true,
+    };
+
+    // 7 words are 49 bits, the 49th is the top bit of a byte padded with 7 zeroes
+    let (out, report) = strip_parity_with_report(&words[..7], scheme, pad)?;
+    assert_eq!(&out[..6], b"parity");
+    assert_eq!(out[6], b'!' & 0b1000_0000);
+    assert_eq!(report.padding_bits, 7);
+
+    let error = strip_parity(&words[..7], scheme).unwrap_err();
+    assert!(error.to_string().contains("1 bits"), "{}", error);
+    Ok(())
+}
+
+#[test]
+fn test_schemes_round_trip() -> Result<()> {
+    // 6510 bytes split evenly into every width below
+    let bytes: Vec<u8> = (0..=255).cycle().take(6510).collect();
+
+    for &data_bits in &[1, 3, 7, 8, 12, 15, 16, 31] {
+        for &parity in &[Parity::Even, Parity::Odd] {
+            for &position in &[BitPosition::Lsb, BitPosition::Msb] {
+                let scheme = ParityScheme {
+                    parity,
+                    position,
+                    data_bits,
+                };
+                let words = add_parity(&bytes, scheme)?;
+                assert_eq!(words.len() % scheme.word_bytes(), 0);
+                assert_eq!(strip_parity(&words, scheme)?, bytes, "{:?}", scheme);
+            }
+        }
+    }
+
+    let too_wide = ParityScheme {
+        data_bits: 32,
+        ..SEVEN_BIT_EVEN_LSB
+    };
+    assert!(add_parity(b"abcd", too_wide).is_err());
+    Ok(())
+}
@@ -0,0 +1,126 @@
+// how much some bytes look like the english text in the onion's documents
+
+// letters per 1000 in english text, a to z
+const LETTERS: [u32; 26] = [
+    82, 15, 28, 43, 127, 22, 20, 61, 70, 2, 8, 40, 24, 67, 75, 19, 1, 60, 63, 91, 28, 10, 24, 2,
+    20, 1,
+];
+
+pub fn is_printable(byte: u8) -> bool {
+    byte.is_ascii_graphic() || byte == b' ' || byte == b'\n' || byte == b'\r' || byte == b'\t'
+}
+
+fn weight(byte: u8) -> f64 {
+    match byte {
+        b'a'..=b'z' => LETTERS[(byte - b'a') as usize] as f64 / 1000.0,
+        b'A'..=b'Z' => LETTERS[(byte - b'A') as usize] as f64 / 1000.0,
+        // about one character in six is a space
+        b' ' => 0.15,
+        b'\n' => 0.02,
+        _ if is_printable(byte) => 0.01,
+        _ => -1.0,
+    }
+}
+
+// the average weight of the bytes. english is around 0.07, anything with control characters or
+// bytes above 0x7f in it quickly goes negative
+pub fn english(bytes: &[u8]) -> f64 {
+    if bytes.is_empty() {
+        return 0.0;
+    }
+    bytes.iter().map(|&byte| weight(byte)).sum::<f64>() / bytes.len() as f64
+}
+
+// the chance of each byte turning up in the text of an onion document, which is english and
+// banners drawn with `=`
+fn text_probability(byte: u8) -> f64 {
+    let english = match byte {
+        b'a'..=b'z' => LETTERS[(byte - b'a') as usize] as f64 / 1000.0 * 0.75,
+        b'A'..=b'Z' => LETTERS[(byte - b'A') as usize] as f64 / 1000.0 * 0.04,
+        b' ' => 0.15,
+        b'\n' => 0.02,
+        b'.' | b',' => 0.01,
+        _ if is_printable(byte) => 0.001,
+        _ => 0.0,
+    };
+    let banner = if byte == b'=' { 1.0 } else { 0.0 };
+    0.85 * english + 0.15 * banner
+}
+
+// or in its Ascii85 payload
+fn ascii85_probability(byte: u8) -> f64 {
+    match byte {
+        b'!'..=b'u' => 1.0 / 85.0,
+        b'~' | b'\n' => 0.001,
+        _ => 0.0,
+    }
+}
+
+// ln of each probability, and anything the model doesn't expect is very unlikely rather than
+// impossible
+fn log_probabilities(probability: impl Fn(u8) -> f64) -> [f64; 256] {
+    let mut table = [0.0; 256];
+    for (byte, log) in table.iter_mut().enumerate() {
+        *log = (probability(byte as u8) + 1e-6).ln();
+    }
+    table
+}
+
+// tables of the log probability of each byte, for scoring a lot of bytes at once
+pub fn text_log_probabilities() -> [f64; 256] {
+    log_probabilities(text_probability)
+}
+
+pub fn ascii85_log_probabilities() -> [f64; 256] {
+    log_probabilities(ascii85_probability)
+}
+
+// for bytes that could be from either part of a document. this only knows which bytes are
+// printable and that spaces and `=` are common, so letters in the payload don't count for or
+// against anything
+pub fn document_log_probabilities() -> [f64; 256] {
+    log_probabilities(|byte| match byte {
+        b' ' => 0.1,
+        b'=' => 0.03,
+        b'\n' => 0.01,
+        _ if is_printable(byte) => 0.86 / 94.0,
+        _ => 0.0,
+    })
+}
+
+#[test]
+fn test_english() {
+    let text = english(b"The quick brown fox jumps over the lazy dog\n");
+    let shouted = english(b"THE QUICK BROWN FOX JUMPS OVER THE LAZY DOG\n");
+    let symbols = english(b"{}[]<>!@#$%^&*()_+{}[]<>!@#$%^&*()_+");
+    let binary = english(&[0x00, 0xff, 0x13, 0x88, 0x7f, 0x01]);
+
+    assert_eq!(text, shouted);
+    assert!(text > symbols, "{} {}", text, symbols);
+    assert!(symbols > binary, "{} {}", symbols, binary);
+    assert!(binary < 0.0);
+    assert_eq!(english(b""), 0.0);
+}
+
+#[cfg(test)]
+fn log_probability(table: &[f64; 256], bytes: &[u8]) -> f64 {
+    bytes.iter().map(|&byte| table[byte as usize]).sum()
+}
+
+#[test]
+fn test_log_probabilities() {
+    let text = b"==[ The quick brown fox jumps over the lazy dog ]==";
+    let payload = b"<~9jqo^BlbD-BleB1DJ+*+F(f,q/0JhKF<GL>Cj@.4Gp$d7F!,L7@<6@)/0JDEF<G%<+EV:2F!,";
+
+    for &(table, typical) in &[
+        (text_log_probabilities(), &text[..]),
+        (ascii85_log_probabilities(), payload),
+        (document_log_probabilities(), text),
+    ] {
+        let score = log_probability(&table, typical);
+        for &mask in &[0x01, 0x20, 0x80] {
+            let changed: Vec<u8> = typical.iter().map(|byte| byte ^ mask).collect();
+            assert!(score > log_probability(&table, &changed), "{:?}", typical);
+        }
+    }
+}
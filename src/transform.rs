@@ -0,0 +1,274 @@
+// byte-at-a-time transforms, chained together and written like `xor:0x55,ror:1`
+use anyhow::{anyhow, bail, ensure, Error, Result};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    // xor:N
+    Xor(u8),
+    // rol:N
+    RotateLeft(u32),
+    // ror:N
+    RotateRight(u32),
+    // rev, bit 7 swaps with bit 0 and so on
+    Reverse,
+    // swap, the high four bits swap with the low four
+    SwapNibbles,
+    // add:N, wrapping
+    Add(u8),
+    // sub:N, wrapping
+    Sub(u8),
+    // perm:76543210, the table lists where each output bit comes from, from bit 7 down to bit 0.
+    // the example leaves the byte alone and perm:01234567 is the same as rev
+    Permute([u8; 8]),
+}
+
+impl Op {
+    pub fn apply(&self, byte: u8) -> u8 {
+        match *self {
+            Op::Xor(mask) => byte ^ mask,
+            Op::RotateLeft(n) => byte.rotate_left(n),
+            Op::RotateRight(n) => byte.rotate_right(n),
+            Op::Reverse => byte.reverse_bits(),
+            Op::SwapNibbles => byte.rotate_left(4),
+            Op::Add(n) => byte.wrapping_add(n),
+            Op::Sub(n) => byte.wrapping_sub(n),
+            Op::Permute(table) => (0..8).fold(0, |out, i| out | ((byte >> table[7 - i]) & 1) << i),
+        }
+    }
+
+    pub fn inverse(&self) -> Op {
+        match *self {
+            Op::RotateLeft(n) => Op::RotateRight(n),
+            Op::RotateRight(n) => Op::RotateLeft(n),
+            Op::Add(n) => Op::Sub(n),
+            Op::Sub(n) => Op::Add(n),
+            Op::Permute(table) => {
+                let mut inverse = [0; 8];
+                for (position, &bit) in table.iter().enumerate() {
+                    // the output bit at 7 - position came from input bit `bit`
+                    inverse[7 - bit as usize] = 7 - position as u8;
+                }
+                Op::Permute(inverse)
+            }
+            op => op,
+        }
+    }
+}
+
+fn parse_number(s: &str) -> Result<u32> {
+    let parsed = if let Some(hex) = s.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(binary) = s.strip_prefix("0b") {
+        u32::from_str_radix(&binary.replace('_', ""), 2)
+    } else {
+        s.parse()
+    };
+    parsed.map_err(|_| anyhow!("{} isn't a number", s))
+}
+
+fn parse_byte(s: &str) -> Result<u8> {
+    let n = parse_number(s)?;
+    ensure!(n <= 0xff, "{} doesn't fit in a byte", s);
+    Ok(n as u8)
+}
+
+impl FromStr for Op {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Op> {
+        let (name, argument) = match s.split_once(':') {
+            Some((name, argument)) => (name.trim(), Some(argument.trim())),
+            None => (s.trim(), None),
+        };
+        let has_argument = argument.is_some();
+        let argument =
+            || argument.ok_or_else(|| anyhow!("{} needs an argument, like {}:1", name, name));
+
+        let op = match name {
+            "xor" => Op::Xor(parse_byte(argument()?)?),
+            "rol" => Op::RotateLeft(parse_number(argument()?)? % 8),
+            "ror" => Op::RotateRight(parse_number(argument()?)? % 8),
+            "add" => Op::Add(parse_byte(argument()?)?),
+            "sub" => Op::Sub(parse_byte(argument()?)?),
+            "perm" => {
+                let digits = argument()?;
+                let mut table = [0; 8];
+                ensure!(
+                    digits.len() == 8,
+                    "a permutation needs 8 bit positions, not {}",
+                    digits
+                );
+                for (entry, digit) in table.iter_mut().zip(digits.chars()) {
+                    *entry = digit
+                        .to_digit(8)
+                        .ok_or_else(|| anyhow!("{} isn't a bit position", digit))?
+                        as u8;
+                }
+                let mut seen = [false; 8];
+                for &bit in &table {
+                    ensure!(!seen[bit as usize], "{} uses bit {} twice", digits, bit);
+                    seen[bit as usize] = true;
+                }
+                Op::Permute(table)
+            }
+            "rev" | "swap" if has_argument => bail!("{} doesn't take an argument", name),
+            "rev" => Op::Reverse,
+            "swap" => Op::SwapNibbles,
+            _ => bail!("unknown transform {}", name),
+        };
+        Ok(op)
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Xor(mask) => write!(f, "xor:{:#04x}", mask),
+            Op::RotateLeft(n) => write!(f, "rol:{}", n),
+            Op::RotateRight(n) => write!(f, "ror:{}", n),
+            Op::Reverse => write!(f, "rev"),
+            Op::SwapNibbles => write!(f, "swap"),
+            Op::Add(n) => write!(f, "add:{}", n),
+            Op::Sub(n) => write!(f, "sub:{}", n),
+            Op::Permute(table) => {
+                write!(f, "perm:")?;
+                table.iter().try_for_each(|bit| write!(f, "{}", bit))
+            }
+        }
+    }
+}
+
+// the ops in order, the first is applied first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transform {
+    ops: Cow<'static, [Op]>,
+}
+
+impl Transform {
+    pub const fn from_static(ops: &'static [Op]) -> Self {
+        Transform {
+            ops: Cow::Borrowed(ops),
+        }
+    }
+
+    pub fn new(ops: Vec<Op>) -> Self {
+        Transform {
+            ops: Cow::Owned(ops),
+        }
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    pub fn apply(&self, byte: u8) -> u8 {
+        self.ops.iter().fold(byte, |byte, op| op.apply(byte))
+    }
+
+    pub fn apply_all(&self, bytes: &[u8]) -> Vec<u8> {
+        // there are only 256 possible inputs, so work each one out once
+        let mut table = [0; 256];
+        for (byte, out) in table.iter_mut().enumerate() {
+            *out = self.apply(byte as u8);
+        }
+        bytes.iter().map(|&byte| table[byte as usize]).collect()
+    }
+
+    // undoes this transform
+    pub fn inverse(&self) -> Transform {
+        Transform::new(self.ops.iter().rev().map(Op::inverse).collect())
+    }
+}
+
+impl FromStr for Transform {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Transform> {
+        let ops = s
+            .split(',')
+            .filter(|op| !op.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<Op>>>()?;
+        Ok(Transform::new(ops))
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, op) in self.ops.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", op)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_ops() {
+    assert_eq!(Op::Xor(0x55).apply(0b1010_1010), 0b1111_1111);
+    assert_eq!(Op::RotateLeft(1).apply(0b1000_0001), 0b0000_0011);
+    assert_eq!(Op::RotateRight(3).apply(0b0000_0001), 0b0010_0000);
+    assert_eq!(Op::Reverse.apply(0b1100_0001), 0b1000_0011);
+    assert_eq!(Op::SwapNibbles.apply(0xa5), 0x5a);
+    assert_eq!(Op::Add(10).apply(250), 4);
+    assert_eq!(Op::Sub(10).apply(4), 250);
+    assert_eq!(
+        Op::Permute([0, 1, 2, 3, 4, 5, 6, 7]).apply(0b1100_0001),
+        0b1000_0011
+    );
+    assert_eq!(
+        Op::Permute([7, 6, 5, 4, 3, 2, 1, 0]).apply(0b1100_0001),
+        0b1100_0001
+    );
+    assert_eq!(
+        Op::Permute([0, 7, 6, 5, 4, 3, 2, 1]).apply(0b0000_0001),
+        Op::RotateRight(1).apply(0b0000_0001)
+    );
+}
+
+#[test]
+fn test_inverse() -> Result<()> {
+    let transform: Transform = "xor:0x55,rol:3,rev,swap,add:7,sub:200,perm:31204756".parse()?;
+    let inverse = transform.inverse();
+    for byte in 0..=255 {
+        assert_eq!(inverse.apply(transform.apply(byte)), byte);
+        assert_eq!(transform.apply(inverse.apply(byte)), byte);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse() -> Result<()> {
+    let transform: Transform = "xor:0x55, ror:1".parse()?;
+    assert_eq!(transform.ops(), [Op::Xor(0x55), Op::RotateRight(1)]);
+    assert_eq!(transform.to_string(), "xor:0x55,ror:1");
+    assert_eq!("".parse::<Transform>()?.apply(42), 42);
+    assert_eq!(
+        "add:0b0000_0011,rol:9".parse::<Transform>()?.ops(),
+        [Op::Add(3), Op::RotateLeft(1)]
+    );
+
+    for bad in &[
+        "xor",
+        "xor:256",
+        "xor:zz",
+        "rev:1",
+        "perm:0123456",
+        "perm:01234567a",
+        "perm:00123456",
+        "perm:01234568",
+        "flip:1",
+    ] {
+        assert!(
+            bad.parse::<Transform>().is_err(),
+            "{} should be rejected",
+            bad
+        );
+    }
+    Ok(())
+}
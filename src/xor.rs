@@ -0,0 +1,532 @@
+// breaking repeating key XOR, by guessing the key length and then each key byte on its own
+use super::score;
+use anyhow::{anyhow, bail, ensure, Error, Result};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub fn hamming(a: &[u8], b: &[u8]) -> u32 {
+    a.iter().zip(b).map(|(a, b)| (a ^ b).count_ones()).sum()
+}
+
+// the average number of bits per byte that differ between neighbouring blocks of length bytes.
+// with the right key length both blocks are XORed with the same key, which cancels out, and text
+// is closer to other text than random bytes are. the tests use it to cross-check key lengths
+#[cfg(test)]
+fn block_distance(bytes: &[u8], length: usize) -> Option<f64> {
+    if length == 0 {
+        return None;
+    }
+    let blocks: Vec<&[u8]> = bytes.chunks_exact(length).collect();
+    if blocks.len() < 2 {
+        return None;
+    }
+    let distance: u32 = blocks
+        .windows(2)
+        .map(|pair| hamming(pair[0], pair[1]))
+        .sum();
+    Some(distance as f64 / ((blocks.len() - 1) * length) as f64)
+}
+
+// the chance that two bytes from the same column are equal, averaged over the columns. with the
+// right key length each column is XORed with one key byte, so it keeps the lumpy distribution of
+// the plaintext
+pub fn index_of_coincidence(bytes: &[u8], length: usize) -> Option<f64> {
+    if length == 0 || bytes.len() < length * 2 {
+        return None;
+    }
+
+    let mut total = 0.0;
+    for column in 0..length {
+        let mut counts = [0u64; 256];
+        let mut n = 0u64;
+        for &byte in bytes.iter().skip(column).step_by(length) {
+            counts[byte as usize] += 1;
+            n += 1;
+        }
+        let pairs: u64 = counts
+            .iter()
+            .map(|&count| count * count.saturating_sub(1))
+            .sum();
+        total += pairs as f64 / (n * (n - 1)).max(1) as f64;
+    }
+    Some(total / length as f64)
+}
+
+// the key lengths from 1 to max_length, most likely first. any multiple of the real length
+// scores about as well as it, so the shortest one close to the best wins
+pub fn guess_key_length(bytes: &[u8], max_length: usize) -> Option<usize> {
+    let coincidences: Vec<(usize, f64)> = (1..=max_length)
+        .filter_map(|length| Some((length, index_of_coincidence(bytes, length)?)))
+        .collect();
+    let best = coincidences
+        .iter()
+        .map(|&(_, coincidence)| coincidence)
+        .fold(f64::NAN, f64::max);
+
+    coincidences
+        .into_iter()
+        .find(|&(_, coincidence)| coincidence >= best * 0.9)
+        .map(|(length, _)| length)
+}
+
+// some plaintext that's known to be in the message, at offset if that's known too
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crib {
+    pub text: Vec<u8>,
+    pub offset: Option<usize>,
+}
+
+impl FromStr for Crib {
+    type Err = Error;
+
+    // `text`, or `text@offset`
+    fn from_str(s: &str) -> Result<Crib> {
+        let (text, offset) = match s.rsplit_once('@') {
+            Some((text, offset)) => match offset.parse() {
+                Ok(offset) => (text, Some(offset)),
+                Err(_) => (s, None),
+            },
+            None => (s, None),
+        };
+        ensure!(!text.is_empty(), "a crib needs some text");
+        Ok(Crib {
+            text: text.as_bytes().to_vec(),
+            offset,
+        })
+    }
+}
+
+impl fmt::Display for Crib {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.text))?;
+        if let Some(offset) = self.offset {
+            write!(f, "@{}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+// scores[column][key byte] is how likely the column is when it's decrypted with that key byte.
+// models holds log probability tables, and model(i) picks the one for the byte at i
+fn column_scores(
+    bytes: &[u8],
+    length: usize,
+    models: &[[f64; 256]],
+    model: impl Fn(usize) -> usize,
+) -> Vec<[f64; 256]> {
+    // count the bytes first, then each key byte only has to go over the 256 possible values
+    let mut counts = vec![vec![[0u32; 256]; models.len()]; length];
+    for (i, &byte) in bytes.iter().enumerate() {
+        counts[i % length][model(i)][byte as usize] += 1;
+    }
+
+    counts
+        .iter()
+        .map(|counts| {
+            let mut scores = [0.0; 256];
+            for (counts, table) in counts.iter().zip(models) {
+                let seen: Vec<(usize, f64)> = (0..256)
+                    .filter(|&byte| counts[byte] > 0)
+                    .map(|byte| (byte, counts[byte] as f64))
+                    .collect();
+                for (key, score) in scores.iter_mut().enumerate() {
+                    *score += seen
+                        .iter()
+                        .map(|&(byte, count)| count * table[byte ^ key])
+                        .sum::<f64>();
+                }
+            }
+            scores
+        })
+        .collect()
+}
+
+fn best_key_byte(scores: &[f64; 256]) -> u8 {
+    (0..=255)
+        .max_by(|&a: &u8, &b| scores[a as usize].total_cmp(&scores[b as usize]))
+        .unwrap_or(0)
+}
+
+// the key bytes the crib implies if it's at offset, None if it contradicts itself or known
+fn crib_key(
+    bytes: &[u8],
+    length: usize,
+    crib: &[u8],
+    offset: usize,
+    known: &[Option<u8>],
+) -> Option<Vec<Option<u8>>> {
+    let mut key = known.to_vec();
+    let cipher = bytes.get(offset..offset + crib.len())?;
+    for (i, (&cipher, &plain)) in cipher.iter().zip(crib).enumerate() {
+        let slot = &mut key[(offset + i) % length];
+        let implied = cipher ^ plain;
+        match *slot {
+            Some(byte) if byte != implied => return None,
+            _ => *slot = Some(implied),
+        }
+    }
+    Some(key)
+}
+
+// the best key for the column scores that fits the cribs. each crib fixes the key bytes under
+// it, at its offset or wherever it fits best
+fn fit(bytes: &[u8], scores: &[[f64; 256]], cribs: &[Crib]) -> Result<Vec<u8>> {
+    let length = scores.len();
+    let best: Vec<u8> = scores.iter().map(best_key_byte).collect();
+    let total = |key: &[Option<u8>]| -> f64 {
+        key.iter()
+            .zip(scores)
+            .zip(&best)
+            .map(|((byte, scores), best)| scores[byte.unwrap_or(*best) as usize])
+            .sum()
+    };
+
+    let mut known = vec![None; length];
+    for crib in cribs {
+        let offsets = match crib.offset {
+            Some(offset) => offset..offset + 1,
+            None => 0..bytes.len().saturating_sub(crib.text.len()) + 1,
+        };
+        let fitted = offsets
+            .filter_map(|offset| crib_key(bytes, length, &crib.text, offset, &known))
+            .map(|key| (total(&key), key))
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+        match fitted {
+            Some((_, key)) => known = key,
+            None => bail!("the crib {} doesn't fit with a {} byte key", crib, length),
+        }
+    }
+
+    Ok(known
+        .iter()
+        .zip(&best)
+        .map(|(byte, best)| byte.unwrap_or(*best))
+        .collect())
+}
+
+// the key of the given length that makes bytes look most like an onion document, with the cribs
+// in it somewhere. the first guess scores every byte as either text or Ascii85, which gets most
+// of the key right. that's enough to tell which lines are payload, and the second guess scores
+// each line as the one it is
+pub fn crack(bytes: &[u8], length: usize, cribs: &[Crib]) -> Result<Vec<u8>> {
+    ensure!(length > 0, "the key can't be empty");
+    ensure!(
+        bytes.len() >= length,
+        "a {} byte key needs at least {} bytes, there are only {}",
+        length,
+        length,
+        bytes.len()
+    );
+
+    let document = [score::document_log_probabilities()];
+    let scores = column_scores(bytes, length, &document, |_| 0);
+    let models = [
+        score::text_log_probabilities(),
+        score::ascii85_log_probabilities(),
+    ];
+    let mut key = fit(bytes, &scores, cribs)?;
+    // each better guess tells the lines apart better, until it stops changing
+    for _ in 0..4 {
+        let payload = payload_lines(&apply(bytes, &key)?, &models);
+        let scores = column_scores(bytes, length, &models, |i| payload[i]);
+        let better = fit(bytes, &scores, cribs)?;
+        if better == key {
+            break;
+        }
+        key = better;
+    }
+    Ok(key)
+}
+
+// which of the models fits the line each byte is on best
+fn payload_lines(plaintext: &[u8], models: &[[f64; 256]]) -> Vec<usize> {
+    let mut lines = Vec::with_capacity(plaintext.len());
+    for line in plaintext.split_inclusive(|&byte| byte == b'\n') {
+        let fit =
+            |table: &[f64; 256]| -> f64 { line.iter().map(|&byte| table[byte as usize]).sum() };
+        let best = (0..models.len())
+            .max_by(|&a, &b| fit(&models[a]).total_cmp(&fit(&models[b])))
+            .unwrap_or(0);
+        lines.extend(line.iter().map(|_| best));
+    }
+    lines
+}
+
+pub fn apply(bytes: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    ensure!(!key.is_empty(), "the key can't be empty");
+    Ok(key
+        .iter()
+        .cycle()
+        .zip(bytes)
+        .map(|(key, byte)| byte ^ key)
+        .collect())
+}
+
+// the shortest length that key nearly repeats at. cracking with a multiple of the real length
+// finds the real key repeated, except that each column has fewer bytes to go on so now and then
+// a key byte comes out wrong. three quarters of the bytes have to match
+pub fn likely_period(key: &[u8]) -> usize {
+    (1..key.len())
+        .filter(|&period| key.len().is_multiple_of(period))
+        .find(|&period| {
+            let matches = key
+                .iter()
+                .zip(&key[period..])
+                .filter(|(a, b)| a == b)
+                .count();
+            matches * 4 >= (key.len() - period) * 3
+        })
+        .unwrap_or(key.len())
+}
+
+pub type Generator = Arc<dyn Fn() -> Box<dyn Iterator<Item = u8>> + Send + Sync>;
+
+// where the bytes to XOR with come from
+#[derive(Clone)]
+pub enum Keystream {
+    // a key repeated over and over
+    Repeating(Vec<u8>),
+    // start, start + step, start + 2 * step and so on, wrapping
+    Rolling { start: u8, step: u8 },
+    // anything else, started again from the beginning for each message
+    Generator(Generator),
+}
+
+impl Keystream {
+    pub fn bytes(&self) -> Box<dyn Iterator<Item = u8> + '_> {
+        match self {
+            Keystream::Repeating(key) => Box::new(key.iter().copied().cycle()),
+            Keystream::Rolling { start, step } => {
+                Box::new((0..).map(move |i: u64| start.wrapping_add(step.wrapping_mul(i as u8))))
+            }
+            Keystream::Generator(generator) => generator(),
+        }
+    }
+
+    // XOR undoes itself, so this both encrypts and decrypts
+    pub fn apply(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let mut keystream = self.bytes();
+        bytes
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| {
+                let key = keystream
+                    .next()
+                    .ok_or_else(|| anyhow!("the keystream ran out after {} bytes", i))?;
+                Ok(byte ^ key)
+            })
+            .collect()
+    }
+}
+
+impl PartialEq for Keystream {
+    fn eq(&self, other: &Keystream) -> bool {
+        match (self, other) {
+            (Keystream::Repeating(a), Keystream::Repeating(b)) => a == b,
+            (
+                Keystream::Rolling { start, step },
+                Keystream::Rolling {
+                    start: other_start,
+                    step: other_step,
+                },
+            ) => start == other_start && step == other_step,
+            (Keystream::Generator(a), Keystream::Generator(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for Keystream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Keystream({})", self)
+    }
+}
+
+// a key in hex, or rolling:<start>:<step>
+impl FromStr for Keystream {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Keystream> {
+        if let Some(rolling) = s.strip_prefix("rolling:") {
+            let (start, step) = rolling.split_once(':').ok_or_else(|| {
+                anyhow!("a rolling key needs a start and a step, like rolling:0:1")
+            })?;
+            let byte = |s: &str| {
+                let parsed = match s.strip_prefix("0x") {
+                    Some(hex) => u8::from_str_radix(hex, 16),
+                    None => s.parse(),
+                };
+                parsed.map_err(|_| anyhow!("{} isn't a byte", s))
+            };
+            return Ok(Keystream::Rolling {
+                start: byte(start)?,
+                step: byte(step)?,
+            });
+        }
+
+        ensure!(
+            !s.is_empty() && s.len().is_multiple_of(2),
+            "a key needs an even number of hex digits, not {}",
+            s.len()
+        );
+        let key = (0..s.len())
+            .step_by(2)
+            .map(|i| {
+                s.get(i..i + 2)
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .ok_or_else(|| anyhow!("{} isn't a key in hex", s))
+            })
+            .collect::<Result<Vec<u8>>>()?;
+        Ok(Keystream::Repeating(key))
+    }
+}
+
+impl fmt::Display for Keystream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Keystream::Repeating(key) => key.iter().try_for_each(|byte| write!(f, "{:02x}", byte)),
+            Keystream::Rolling { start, step } => write!(f, "rolling:{}:{}", start, step),
+            Keystream::Generator(_) => write!(f, "generator"),
+        }
+    }
+}
+
+#[cfg(test)]
+const PLAINTEXT: &[u8] = b"==[ Layer 9/9: Somewhere Else ]=============================
+
+Exclusive Or (XOR) is another bitwise operation. It's often used in cryptography to combine two
+sources of binary data -- for example, to combine binary data with a secret key, resulting in
+scrambled output data. What makes XOR useful, compared to other bitwise operations, is that the
+scrambling can be reversed. If you XOR the scrambled data with the same key, you get the
+original data back. The key is repeated over and over, for as long as the data goes on.
+
+==[ Payload ]===============================================
+
+<~:2+3L+EqaECEXg\"BOQ!*G@>P86=FqH+?250+EqL5@qZupDf'',+DG^9A8,XfATD@\"F<Ga8EbSs\"FE9&W<+ohc6\"FnCAM6>
+j@qfX:2'@'NEbo0*@<?4%DBO%7AKYo'+CT.u+EqaECEXg\"BOQ!*@ps1iARlp*D]iIq2)dsM0ll:2+3L+EqaECEXg\"BOQ~>
+";
+
+#[test]
+fn test_hamming() {
+    assert_eq!(hamming(b"this is a test", b"wokka wokka!!!"), 37);
+    assert_eq!(hamming(b"", b""), 0);
+}
+
+#[test]
+fn test_key_length() -> Result<()> {
+    let key = b"not a very secret key, but long";
+    let cipher = apply(PLAINTEXT, key)?;
+    assert_eq!(guess_key_length(&cipher, 64), Some(key.len()));
+
+    let distances: Vec<f64> = (1..=40)
+        .filter_map(|length| block_distance(&cipher, length))
+        .collect();
+    let closest = distances
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, _)| i + 1);
+    assert_eq!(closest, Some(key.len()));
+    assert_eq!(block_distance(&cipher, 0), None);
+
+    assert_eq!(index_of_coincidence(b"ab", 2), None);
+    assert_eq!(guess_key_length(b"", 8), None);
+    Ok(())
+}
+
+#[test]
+fn test_crack() -> Result<()> {
+    for key in &[&b"Z"[..], b"key", b"\x00\xff\x13\x37 secret \x80"] {
+        let cipher = apply(PLAINTEXT, key)?;
+        assert_eq!(crack(&cipher, key.len(), &[])?, *key);
+    }
+    assert!(crack(b"abc", 4, &[]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_cribs() -> Result<()> {
+    // a long key leaves too few bytes in each column to go on, so the cribs have to help
+    let key: Vec<u8> = (0..120).map(|i| (i * 37 + 11) as u8).collect();
+    let cipher = apply(PLAINTEXT, &key)?;
+    let cribs = vec![
+        "==[ Layer @0".parse()?,
+        "Exclusive Or (XOR) is another bitwise operation. It's often used in cryptography"
+            .parse()?,
+        "sources of binary data -- for example, to combine binary data with a secret key, res"
+            .parse()?,
+    ];
+    let cracked = crack(&cipher, key.len(), &cribs)?;
+    let plaintext = apply(&cipher, &cracked)?;
+    // the first crib is at the start, the others had to be found
+    assert_eq!(&plaintext[..10], &PLAINTEXT[..10]);
+    assert_eq!(&plaintext[62..142], &PLAINTEXT[62..142]);
+    assert_eq!(&plaintext[158..242], &PLAINTEXT[158..242]);
+
+    let contradiction = Crib {
+        text: b"ab".to_vec(),
+        offset: Some(0),
+    };
+    assert!(crack(&cipher, 1, &[contradiction]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_parse_crib() -> Result<()> {
+    assert_eq!(
+        "==[ Layer@0".parse::<Crib>()?,
+        Crib {
+            text: b"==[ Layer".to_vec(),
+            offset: Some(0)
+        }
+    );
+    assert_eq!("a@b".parse::<Crib>()?.offset, None);
+    assert_eq!("a@b".parse::<Crib>()?.text, b"a@b");
+    assert_eq!(
+        "me@example.com@12".parse::<Crib>()?.to_string(),
+        "me@example.com@12"
+    );
+    assert!("@3".parse::<Crib>().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_likely_period() {
+    assert_eq!(likely_period(b"abcabcabc"), 3);
+    assert_eq!(likely_period(b"aaaa"), 1);
+    assert_eq!(likely_period(b"abcab"), 5);
+    assert_eq!(likely_period(b""), 0);
+    assert_eq!(likely_period(b"abcdabcdabcdabcd"), 4);
+    assert_eq!(likely_period(b"abcdabcdabcdabXd"), 4);
+    assert_eq!(likely_period(b"abcdabXYabcdabcd"), 8);
+}
+
+#[test]
+fn test_keystream() -> Result<()> {
+    let key: Keystream = "0a0b0c".parse()?;
+    assert_eq!(key, Keystream::Repeating(vec![0x0a, 0x0b, 0x0c]));
+    assert_eq!(key.to_string(), "0a0b0c");
+    assert_eq!(key.apply(&[0; 4])?, [0x0a, 0x0b, 0x0c, 0x0a]);
+
+    let rolling: Keystream = "rolling:250:0x03".parse()?;
+    assert_eq!(rolling.apply(&[0; 4])?, [250, 253, 0, 3]);
+    assert_eq!(rolling.to_string().parse::<Keystream>()?, rolling);
+
+    // an 8 bit counter that stops
+    let generator: Generator = Arc::new(|| Box::new(0..=255u8));
+    let counter = Keystream::Generator(generator);
+    assert_eq!(counter.apply(b"\x01\x01")?, [1, 0]);
+    assert_eq!(counter.apply(&[1; 256])?[255], 254);
+    assert!(counter.apply(&[1; 257]).is_err());
+    assert_eq!(counter, counter.clone());
+
+    for bad in &["", "abc", "zz", "rolling:1", "rolling:1:256"] {
+        assert!(
+            bad.parse::<Keystream>().is_err(),
+            "{} should be rejected",
+            bad
+        );
+    }
+    Ok(())
+}